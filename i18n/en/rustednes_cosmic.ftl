@@ -1,8 +1,37 @@
 app-title = RustedNES Cosmic
 file = File
 open-rom = Open ROM
+apply-patch = Apply Patch…
+recent-roms = Recent ROMs
+no-recent-roms = No Recent ROMs
+clear-recent = Clear Recent
+take-screenshot = Take Screenshot
+screenshot-saved = Saved screenshot to { $path }
+screenshot-error = Couldn't save screenshot: { $reason }
+start-recording = Start Recording
+stop-recording = Stop Recording
+record-audio = Record Audio
+stop-recording-audio = Stop Recording Audio
+recording-indicator = ● REC
+recording-saved = Saved recording to { $path }
+recording-error = Couldn't record: { $reason }
+recent-rom-missing = { $file } no longer exists
 about = About
 view = View
+palette = Palette
+palette-default = Default
+palette-fceux = FCEUX
+palette-nestopia = Nestopia
+palette-smooth = Smooth (FirebrandX)
+palette-custom = Custom
+palette-load = Load Palette File…
+palette-error = Couldn't load palette: { $reason }
+ntsc-filter = NTSC Filter
+ntsc-off = Off
+ntsc-composite = Composite
+ntsc-svideo = S-Video
+ntsc-rgb = RGB
+ntsc-monochrome = Monochrome
 page-id = Page { $num }
 git-description = Git commit {$hash} on {$date}
 
@@ -10,3 +39,113 @@ emulation = Emulation
 pause = Pause
 unpause = Unpause
 reset = Reset
+save-state = Save State
+load-state = Load State
+save-slot = Save Slot
+movie = Movie
+record-movie-from-power-on = Record From Power On…
+record-movie-from-here = Record From Here…
+play-movie = Play…
+stop-movie = Stop
+movie-recording = ● { $frame }
+movie-playing = ▶ { $frame } / { $length }
+movie-finished = Movie finished
+movie-saved = Saved movie to { $path }
+movie-rom-mismatch = This movie was recorded with a different ROM and may not play back correctly
+movie-error = Movie error: { $reason }
+slot = Slot { $num }
+state-saved = Saved state to slot { $slot }
+state-loaded = Loaded state from slot { $slot }
+state-save-error = Couldn't save state: { $reason }
+state-load-error = Couldn't load state: { $reason }
+state-load-during-movie = Stop the movie before loading a state
+speed = Speed
+speed-multiplier = { $multiplier }×
+speed-unthrottled = Unthrottled
+frame-advance = Frame Advance
+step-instruction = Step Instruction
+
+settings = Settings
+player-1-controls = Player 1 Controls
+player-2-controls = Player 2 Controls
+press-a-key = Press a key…
+press-a-button = Press a button…
+unbound = Unbound
+reset-to-defaults = Reset to Defaults
+button-a = A
+button-b = B
+button-select = Select
+button-start = Start
+button-up = Up
+button-down = Down
+button-left = Left
+button-right = Right
+port = Port
+unassigned = Unassigned
+player-1 = Player 1
+player-2 = Player 2
+audio = Audio
+audio-output = Output
+audio-system-default = System Default
+audio-none = None
+audio-wav-file = WAV File
+rewind = Rewind
+rewind-interval = Snapshot Interval
+rewind-interval-frames = { $frames ->
+    [one] Every frame
+   *[other] Every { $frames } frames
+}
+rewind-buffer = Memory
+rewind-buffer-size = { $mb } MB
+rewind-off = Off
+screenshots = Screenshots
+screenshot-folder = Folder
+screenshot-size = Size
+screenshot-scale = { $scale }×
+recordings = Recordings
+recording-folder = Folder
+
+rom-load-error = Couldn't open { $file }: { $reason }
+drop-to-open = Drop to open ROM
+drop-to-patch = Drop to apply patch
+patch-needs-game = Open a game before applying a patch
+patch-applied = Applied patch { $file }
+patch-skipped = Couldn't apply { $file }, so the game was loaded without it
+unsupported-file = { $file } isn't a ROM or patch
+choose-rom = Choose a ROM
+archive-has-several-roms = { $file } contains more than one ROM. Which one do you want to play?
+cancel = Cancel
+
+search-library = Search
+choose-rom-folder = Choose ROM Folder…
+rescan = Rescan
+no-rom-folder = Choose a folder containing your ROMs to build the library.
+scanning-library = Scanning…
+library-empty = No ROMs were found in the ROM folder.
+never-played = Never
+library-entry-details = Mapper { $mapper } · { $size } KiB · Last played: { $played }
+
+rom-info = ROM Info
+no-game-loaded = No game is loaded.
+path = Path
+patch = Patch
+cartridge = Cartridge
+mapper = Mapper
+prg-rom = PRG ROM
+chr-rom = CHR ROM
+chr-ram = None (CHR RAM)
+size-kib = { $size } KiB
+mirroring = Mirroring
+mirroring-horizontal = Horizontal
+mirroring-vertical = Vertical
+mirroring-four-screen = Four-screen
+battery = Battery
+region = Region
+header-format = Header Format
+header-corrected = { $format }, corrected from the ROM database
+corrected-value = { $value } (header said { $original })
+rom-database = ROM Database
+database-match = Match
+no-match = Not found
+yes = Yes
+no = No
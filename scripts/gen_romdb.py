@@ -0,0 +1,169 @@
+#!/usr/bin/env python3
+# SPDX-License-Identifier: MPL-2.0
+
+"""Generates resources/romdb/nes.csv from a database of known NES dumps.
+
+Two databases are understood:
+
+- TetaNES's game_database.txt, shipped in the tetanes-core crate under the MIT licence. Each
+  line has the CRC32 of the PRG and CHR ROM together, the region, mapper, bank counts, battery,
+  mirroring and the dump's file name. This is what the bundled file is generated from.
+- NewRisingSun's nes20db.xml, distributed with the NES 2.0 header specification. Every <game> in
+  it has an XML comment naming the dump, a <rom> element with the CRC32 of the PRG and CHR ROM
+  together, a <pcb> element with the mapper, mirroring and battery, and a <console> element with
+  the region. It doesn't state a licence, so check its terms before distributing a build made
+  from it.
+
+Usage: scripts/gen_romdb.py path/to/game_database.txt > resources/romdb/nes.csv
+"""
+
+import argparse
+import os
+import sys
+import xml.etree.ElementTree as ET
+
+HEADER = """\
+# Known good dumps, used to correct bad iNES headers.
+#
+# Generated by scripts/gen_romdb.py from {source}.
+# Don't edit by hand; regenerate instead.
+#
+# Each line is: PRG+CHR CRC32,mapper,mirroring,battery,region,title
+#
+# The CRC32 covers the PRG and CHR ROM only, without the header or trainer, so it matches
+# headerless No-Intro checksums. Mirroring is H, V, 4 (four-screen) or - (left to the mapper),
+# battery is 0 or 1, and region is NTSC, PAL, Multi or Dendy. The title is last so it may
+# contain commas."""
+
+TETANES_NOTICE = """
+#
+# The data comes from TetaNES (https://github.com/lukexor/tetanes), used under the MIT licence:
+#
+# Copyright (c) Luke Petherbridge <me@lukeworks.tech>
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy of this software
+# and associated documentation files (the "Software"), to deal in the Software without
+# restriction, including without limitation the rights to use, copy, modify, merge, publish,
+# distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the
+# Software is furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all copies or
+# substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING
+# BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+# NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+# DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE."""
+
+# Anything else is mirroring the mapper switches itself, where the header bits don't matter.
+HARDWIRED_MIRRORING = {"H", "V", "4"}
+NES20DB_REGIONS = {"0": "NTSC", "1": "PAL", "2": "Multi", "3": "Dendy"}
+
+# TetaNES lists a mirroring for every game, including those whose mapper switches it.
+TETANES_MIRRORING = {"Horizontal": "H", "Vertical": "V", "FourScreen": "4"}
+SWITCHED_MIRRORING_MAPPERS = {
+    1, 4, 5, 7, 9, 10, 16, 18, 19, 21, 22, 23, 24, 25, 26, 32, 33, 48, 64, 65, 67, 68, 69, 75,
+    80, 82, 85, 112, 113, 118, 119, 152, 153, 157, 159, 243,
+}
+
+
+def line(crc, mapper, mirroring, battery, region, name):
+    return f"{crc:08X},{mapper},{mirroring},{'1' if battery else '0'},{region},{name}"
+
+
+def nes20db_title(game):
+    """The dump name from the comment at the start of a game, without its folder or extension."""
+    for child in game:
+        if child.tag is ET.Comment:
+            name = child.text.strip().replace("\\", "/").rsplit("/", 1)[-1]
+            return os.path.splitext(name)[0]
+    return None
+
+
+def nes20db_entry(game):
+    rom = game.find("rom")
+    pcb = game.find("pcb")
+    console = game.find("console")
+    if rom is None or pcb is None or console is None:
+        return None
+
+    mirroring = pcb.get("mirroring")
+    if mirroring not in HARDWIRED_MIRRORING:
+        mirroring = "-"
+    region = NES20DB_REGIONS.get(console.get("region"))
+    name = nes20db_title(game)
+    if region is None or not name:
+        return None
+
+    crc = int(rom.get("crc32"), 16)
+    battery = pcb.get("battery") == "1"
+    return crc, line(crc, int(pcb.get("mapper")), mirroring, battery, region, name)
+
+
+def read_nes20db(path):
+    parser = ET.XMLParser(target=ET.TreeBuilder(insert_comments=True))
+    root = ET.parse(path, parser).getroot()
+
+    source = os.path.basename(path)
+    date = root.get("date")
+    if date:
+        source += f" ({date})"
+    return source, "", [nes20db_entry(game) for game in root.iter("game")]
+
+
+def tetanes_entry(text):
+    # CRC, Region, Mapper, Sub-Mapper, ChrBanks, PrgRomBanks, PrgRamBanks, Battery, Mirroring,
+    # SubMapper, Title
+    fields = [field.strip() for field in text.split(",", 9)]
+    if len(fields) != 10:
+        return None
+    crc, region, mapper, _, _, _, _, battery, mirroring, name = fields
+
+    mapper = int(mapper)
+    mirroring = TETANES_MIRRORING.get(mirroring)
+    if mirroring is None or region not in ("NTSC", "PAL"):
+        return None
+    if mirroring != "4" and mapper in SWITCHED_MIRRORING_MAPPERS:
+        mirroring = "-"
+    name = os.path.splitext(name.strip('"'))[0]
+
+    crc = int(crc, 16)
+    return crc, line(crc, mapper, mirroring, battery == "true", region, name)
+
+
+def read_tetanes(path):
+    with open(path, encoding="utf-8") as file:
+        lines = [text for text in file if text.strip() and not text.startswith("#")]
+    source = "TetaNES's " + os.path.basename(path)
+    return source, TETANES_NOTICE, [tetanes_entry(text) for text in lines]
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("database", help="path to game_database.txt or nes20db.xml")
+    args = parser.parse_args()
+
+    if args.database.endswith(".xml"):
+        source, notice, parsed = read_nes20db(args.database)
+    else:
+        source, notice, parsed = read_tetanes(args.database)
+
+    entries = {}
+    skipped = 0
+    for entry in parsed:
+        if entry is None:
+            skipped += 1
+            continue
+        # Some dumps share their PRG and CHR ROM. Keep the first, as the lookup can only return one.
+        entries.setdefault(*entry)
+
+    print(HEADER.format(source=source) + notice)
+    for crc in sorted(entries):
+        print(entries[crc])
+
+    print(f"wrote {len(entries)} games, skipped {skipped}", file=sys.stderr)
+
+
+if __name__ == "__main__":
+    main()
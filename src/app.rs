@@ -1,36 +1,102 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::archive::{self, ArchiveKind};
+use crate::audio::{self, AudioBackend, AudioOutput};
 use crate::config::Config;
-use crate::emulator::{load_rom, Emulator};
+use crate::emulator::{
+    load_rom, EmulationSpeed, Emulator, InputSource, MovieProgress, SAVE_STATE_SLOTS,
+};
 use crate::fl;
+use crate::gamepad::{
+    self, AxisState, GamepadAxis, GamepadButton, GamepadEvent, GamepadId, GamepadInfo,
+    GamepadMapping,
+};
+use crate::keymap::{self, KeyBindings, NesButton, Port};
+use crate::library::{self, LibraryEntry};
+use crate::movie::{self, Movie, MovieStart};
+use crate::ntsc::NtscPreset;
+use crate::palette::{Palette, PaletteChoice};
+use crate::patch::{self, PatchFormat};
+use crate::recording::{self, RecordingKind};
+use crate::rewind;
+use crate::rom::{self, Mirroring, Region, Rom};
+use crate::screenshot;
+use chrono::{DateTime, Local};
 use cosmic::app::context_drawer;
 use cosmic::cosmic_config::{self, CosmicConfigEntry};
 use cosmic::iced::alignment::{Horizontal, Vertical};
-use cosmic::iced::keyboard::key::{Code as KeyCode, Physical};
-use cosmic::iced::keyboard::{Event as KeyEvent, Modifiers};
-use cosmic::iced::{event, window, Alignment, Event, Length, Subscription};
+use cosmic::iced::keyboard::key::{Code as KeyCode, Named, Physical};
+use cosmic::iced::keyboard::{Event as KeyEvent, Key, Modifiers};
+use cosmic::iced::{event, window, Alignment, Color, ContentFit, Event, Length, Subscription};
 use cosmic::iced_core::image;
 use cosmic::prelude::*;
+use cosmic::widget::menu::key_bind::{KeyBind, Modifier};
+use cosmic::widget::toaster::{Toast, ToastId, Toasts};
 use cosmic::widget::{self, menu};
 use cosmic::{cosmic_theme, theme};
 use rfd::AsyncFileDialog;
-use rustednes_core::cartridge::Cartridge;
-use rustednes_core::input::Button;
+use rustednes_core::apu::SAMPLE_RATE as APU_SAMPLE_RATE;
 use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 const REPOSITORY: &str = env!("CARGO_PKG_REPOSITORY");
 const APP_ICON: &[u8] = include_bytes!("../resources/icons/hicolor/scalable/apps/icon.svg");
 
+/// Held down to rewind the emulation.
+const REWIND_KEY: KeyCode = KeyCode::Backspace;
+
+/// Held down to run the emulation faster than normal.
+const FAST_FORWARD_KEY: KeyCode = KeyCode::Tab;
+
+/// How many ROMs are listed in the recent ROMs menu.
+const MAX_RECENT_ROMS: usize = 10;
+
 /// The application model stores app-specific state used to describe its interface and
 /// drive its logic.
 pub struct AppModel {
     core: cosmic::Core,
     context_page: ContextPage,
     key_binds: HashMap<menu::KeyBind, MenuAction>,
+    config_handler: Option<cosmic_config::Config>,
     config: Config,
     emulator: Option<Emulator>,
     opening_file: bool,
+    rebinding: Option<(Port, NesButton)>,
+    gamepads: BTreeMap<GamepadId, ConnectedGamepad>,
+    gamepad_rebinding: Option<(GamepadId, NesButton)>,
+    port_options: Vec<String>,
+    audio_backend_options: Vec<String>,
+    screenshot_scale_options: Vec<String>,
+    rewind_interval_options: Vec<String>,
+    rewind_buffer_options: Vec<String>,
+    /// Audio backend and WAV file given on the command line, which take precedence over the
+    /// config.
+    audio_backend_override: Option<AudioBackend>,
+    audio_file_override: Option<PathBuf>,
+    toasts: Toasts<Message>,
+    library: Vec<LibraryEntry>,
+    library_filter: String,
+    scanning_library: bool,
+    /// The file being dragged over the window, if any.
+    drop_target: Option<PathBuf>,
+    /// An archive holding several ROMs that the user is being asked to pick from.
+    archive_prompt: Option<ArchivePrompt>,
+    /// The entry of the running game's archive, if it was loaded from one.
+    rom_entry: Option<String>,
+}
+
+struct ArchivePrompt {
+    path: PathBuf,
+    entries: Vec<String>,
+    patch: Option<PathBuf>,
+}
+
+/// A game controller that is currently plugged in.
+struct ConnectedGamepad {
+    info: GamepadInfo,
+    port: Option<Port>,
+    axes: AxisState,
 }
 
 /// Messages emitted by the application and its widgets.
@@ -42,16 +108,109 @@ pub enum Message {
     LaunchUrl(String),
     OpenFileDialog,
     OpenFileResult(Option<PathBuf>),
-    KeyDown(Modifiers, KeyCode),
+    OpenPatchDialog,
+    OpenPatchResult(Option<PathBuf>),
+    KeyDown(Modifiers, Key, KeyCode),
     KeyUp(Modifiers, KeyCode),
     Tick,
     ToggleEmulation,
     ResetEmulation,
+    SaveState,
+    LoadState,
+    SelectSaveSlot(u8),
+    FlushSram,
+    SetSpeed(EmulationSpeed),
+    FrameAdvance,
+    StepInstruction,
+    RebindButton(Port, NesButton),
+    ResetKeyBindings,
+    GamepadConnected(GamepadId, GamepadInfo),
+    GamepadDisconnected(GamepadId),
+    GamepadButtonChanged(GamepadId, GamepadButton, bool),
+    GamepadAxisChanged(GamepadId, GamepadAxis, f32),
+    RebindGamepadButton(GamepadId, NesButton),
+    AssignGamepadPort(GamepadId, Option<Port>),
+    SetAudioBackend(AudioBackend),
+    TakeScreenshot,
+    ChooseScreenshotDir,
+    ScreenshotDirChosen(Option<PathBuf>),
+    SetScreenshotScale(u32),
+    SetRewindInterval(u32),
+    SetRewindBuffer(u32),
+    SetPalette(PaletteChoice),
+    ChoosePaletteFile,
+    PaletteFileChosen(Option<PathBuf>),
+    SetNtscFilter(Option<NtscPreset>),
+    ToggleRecording(RecordingKind),
+    RecordMovie(MovieStart),
+    RecordMoviePathChosen(MovieStart, Option<PathBuf>),
+    PlayMovie,
+    PlayMovieResult(Option<PathBuf>),
+    StopMovie,
+    ChooseRecordingDir,
+    RecordingDirChosen(Option<PathBuf>),
+    CloseToast(ToastId),
+    ScanLibrary,
+    LibraryScanned(Vec<LibraryEntry>),
+    LibraryFilterChanged(String),
+    OpenLibraryEntry(PathBuf),
+    ChooseRomDir,
+    RomDirChosen(Option<PathBuf>),
+    OpenRecentRom(usize),
+    ClearRecentRoms,
+    FileHovered(PathBuf),
+    FilesHoveredLeft,
+    FileDropped(PathBuf),
+    OpenArchiveEntry(usize),
+    CancelArchivePrompt,
 }
 
+/// Action sent over D-Bus by a second launch to open its ROM in the running instance.
+pub const OPEN_ROM_ACTION: &str = "open-rom";
+
 #[derive(Default)]
 pub struct Flags {
-    pub rom: Option<(Cartridge, PathBuf)>,
+    pub rom_path: Option<PathBuf>,
+    pub patch_path: Option<PathBuf>,
+    pub audio_backend: Option<AudioBackend>,
+    pub audio_file: Option<PathBuf>,
+    /// Set when there is a ROM to hand over to an already running instance.
+    pub action: Option<String>,
+    pub args: Vec<String>,
+}
+
+impl Flags {
+    pub fn new(rom_path: Option<PathBuf>, patch_path: Option<PathBuf>) -> Self {
+        // The running instance has its own working directory, so relative paths won't resolve.
+        let rom_path = rom_path.map(|path| std::path::absolute(&path).unwrap_or(path));
+        let patch_path = patch_path.map(|path| std::path::absolute(&path).unwrap_or(path));
+        let args = rom_path
+            .iter()
+            .chain(&patch_path)
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+
+        Self {
+            action: rom_path.as_ref().map(|_| OPEN_ROM_ACTION.to_string()),
+            rom_path,
+            patch_path,
+            args,
+            ..Self::default()
+        }
+    }
+}
+
+impl cosmic::app::CosmicFlags for Flags {
+    type SubCommand = String;
+    type Args = Vec<String>;
+
+    fn action(&self) -> Option<&String> {
+        self.action.as_ref()
+    }
+
+    fn args(&self) -> Vec<&str> {
+        self.args.iter().map(String::as_str).collect()
+    }
 }
 
 /// Create a COSMIC application from the app model
@@ -77,51 +236,158 @@ impl cosmic::Application for AppModel {
     }
 
     fn init(core: cosmic::Core, flags: Self::Flags) -> (Self, Task<cosmic::Action<Self::Message>>) {
+        let config_handler = cosmic_config::Config::new(Self::APP_ID, Config::VERSION).ok();
+        let config = config_handler
+            .as_ref()
+            .map(|context| match Config::get_entry(context) {
+                Ok(config) => config,
+                Err((errors, config)) => {
+                    for why in errors {
+                        tracing::error!(%why, "error loading app config");
+                    }
+
+                    config
+                }
+            })
+            .unwrap_or_default();
+
         let mut app = AppModel {
             core,
             context_page: ContextPage::default(),
-            key_binds: HashMap::new(),
-            config: cosmic_config::Config::new(Self::APP_ID, Config::VERSION)
-                .map(|context| match Config::get_entry(&context) {
-                    Ok(config) => config,
-                    Err((errors, config)) => {
-                        for why in errors {
-                            tracing::error!(%why, "error loading app config");
-                        }
-
-                        config
-                    }
-                })
-                .unwrap_or_default(),
-            emulator: flags
-                .rom
-                .map(|(rom, rom_path)| Emulator::new(rom, rom_path, AppModel::keymap())),
+            key_binds: AppModel::key_binds(),
+            config_handler,
+            config,
+            emulator: None,
             opening_file: false,
+            rebinding: None,
+            gamepads: BTreeMap::new(),
+            gamepad_rebinding: None,
+            port_options: vec![fl!("unassigned"), fl!("player-1"), fl!("player-2")],
+            audio_backend_options: AudioBackend::ALL
+                .into_iter()
+                .map(audio_backend_label)
+                .collect(),
+            screenshot_scale_options: screenshot::SCALES
+                .into_iter()
+                .map(|scale| fl!("screenshot-scale", scale = scale))
+                .collect(),
+            rewind_interval_options: rewind::INTERVALS
+                .into_iter()
+                .map(|frames| fl!("rewind-interval-frames", frames = frames))
+                .collect(),
+            rewind_buffer_options: rewind::BUFFER_SIZES_MB
+                .into_iter()
+                .map(|mb| match mb {
+                    0 => fl!("rewind-off"),
+                    mb => fl!("rewind-buffer-size", mb = mb),
+                })
+                .collect(),
+            audio_backend_override: flags.audio_backend,
+            audio_file_override: flags.audio_file,
+            toasts: Toasts::new(Message::CloseToast),
+            library: Vec::new(),
+            library_filter: String::new(),
+            scanning_library: false,
+            drop_target: None,
+            archive_prompt: None,
+            rom_entry: None,
         };
 
-        let command = app.update_title();
+        app.prune_recent_roms();
+
+        let mut commands = vec![app.update_title(), app.scan_library()];
+        if let Some(rom_path) = flags.rom_path {
+            commands.push(app.open_patched_rom(rom_path, flags.patch_path));
+        }
 
-        (app, command)
+        (app, Task::batch(commands))
     }
 
     fn header_start(&self) -> Vec<Element<Self::Message>> {
+        let recording = self.recording();
+        let video_label = if recording == Some(RecordingKind::Video) {
+            fl!("stop-recording")
+        } else {
+            fl!("start-recording")
+        };
+        let audio_label = if recording == Some(RecordingKind::Audio) {
+            fl!("stop-recording-audio")
+        } else {
+            fl!("record-audio")
+        };
+
         let mut menu_trees = vec![
             menu::Tree::with_children(
                 menu::root(fl!("file")),
                 menu::items(
                     &self.key_binds,
-                    vec![menu::Item::Button(
-                        fl!("open-rom"),
-                        None,
-                        MenuAction::OpenFile,
-                    )],
+                    vec![
+                        menu::Item::Button(fl!("open-rom"), None, MenuAction::OpenFile),
+                        menu::Item::Folder(fl!("recent-roms"), self.recent_rom_items()),
+                        if self.emulator.is_some() {
+                            menu::Item::Button(fl!("apply-patch"), None, MenuAction::OpenPatch)
+                        } else {
+                            menu::Item::ButtonDisabled(
+                                fl!("apply-patch"),
+                                None,
+                                MenuAction::OpenPatch,
+                            )
+                        },
+                        menu::Item::Divider,
+                        if self.emulator.is_some() {
+                            menu::Item::Button(
+                                fl!("take-screenshot"),
+                                None,
+                                MenuAction::TakeScreenshot,
+                            )
+                        } else {
+                            menu::Item::ButtonDisabled(
+                                fl!("take-screenshot"),
+                                None,
+                                MenuAction::TakeScreenshot,
+                            )
+                        },
+                        if self.emulator.is_some() {
+                            menu::Item::Button(
+                                video_label,
+                                None,
+                                MenuAction::ToggleRecording(RecordingKind::Video),
+                            )
+                        } else {
+                            menu::Item::ButtonDisabled(
+                                video_label,
+                                None,
+                                MenuAction::ToggleRecording(RecordingKind::Video),
+                            )
+                        },
+                        if self.emulator.is_some() {
+                            menu::Item::Button(
+                                audio_label,
+                                None,
+                                MenuAction::ToggleRecording(RecordingKind::Audio),
+                            )
+                        } else {
+                            menu::Item::ButtonDisabled(
+                                audio_label,
+                                None,
+                                MenuAction::ToggleRecording(RecordingKind::Audio),
+                            )
+                        },
+                    ],
                 ),
             ),
             menu::Tree::with_children(
                 menu::root(fl!("view")),
                 menu::items(
                     &self.key_binds,
-                    vec![menu::Item::Button(fl!("about"), None, MenuAction::About)],
+                    vec![
+                        menu::Item::Folder(fl!("palette"), self.palette_items()),
+                        menu::Item::Folder(fl!("ntsc-filter"), self.ntsc_filter_items()),
+                        menu::Item::Divider,
+                        menu::Item::Button(fl!("rom-info"), None, MenuAction::RomInfo),
+                        menu::Item::Button(fl!("settings"), None, MenuAction::Settings),
+                        menu::Item::Button(fl!("about"), None, MenuAction::About),
+                    ],
                 ),
             ),
         ];
@@ -142,6 +408,72 @@ impl cosmic::Application for AppModel {
                             MenuAction::ToggleEmulation,
                         ),
                         menu::Item::Button(fl!("reset"), None, MenuAction::ResetEmulation),
+                        menu::Item::Button(fl!("frame-advance"), None, MenuAction::FrameAdvance),
+                        menu::Item::Button(
+                            fl!("step-instruction"),
+                            None,
+                            MenuAction::StepInstruction,
+                        ),
+                        menu::Item::Divider,
+                        menu::Item::Button(fl!("save-state"), None, MenuAction::SaveState),
+                        menu::Item::Button(fl!("load-state"), None, MenuAction::LoadState),
+                        menu::Item::Folder(
+                            fl!("save-slot"),
+                            (0..SAVE_STATE_SLOTS)
+                                .map(|slot| {
+                                    menu::Item::CheckBox(
+                                        fl!("slot", num = slot),
+                                        None,
+                                        emulator.save_slot() == slot,
+                                        MenuAction::SelectSaveSlot(slot),
+                                    )
+                                })
+                                .collect(),
+                        ),
+                        menu::Item::Divider,
+                        menu::Item::Folder(
+                            fl!("speed"),
+                            EmulationSpeed::ALL
+                                .into_iter()
+                                .map(|speed| {
+                                    menu::Item::CheckBox(
+                                        speed_label(speed),
+                                        None,
+                                        emulator.speed() == speed,
+                                        MenuAction::SetSpeed(speed),
+                                    )
+                                })
+                                .collect(),
+                        ),
+                        menu::Item::Folder(
+                            fl!("movie"),
+                            vec![
+                                menu::Item::Button(
+                                    fl!("record-movie-from-power-on"),
+                                    None,
+                                    MenuAction::RecordMovie(MovieStart::PowerOn),
+                                ),
+                                menu::Item::Button(
+                                    fl!("record-movie-from-here"),
+                                    None,
+                                    MenuAction::RecordMovie(MovieStart::CurrentState),
+                                ),
+                                menu::Item::Button(fl!("play-movie"), None, MenuAction::PlayMovie),
+                                if emulator.movie_progress().is_some() {
+                                    menu::Item::Button(
+                                        fl!("stop-movie"),
+                                        None,
+                                        MenuAction::StopMovie,
+                                    )
+                                } else {
+                                    menu::Item::ButtonDisabled(
+                                        fl!("stop-movie"),
+                                        None,
+                                        MenuAction::StopMovie,
+                                    )
+                                },
+                            ],
+                        ),
                     ],
                 ),
             ));
@@ -152,6 +484,73 @@ impl cosmic::Application for AppModel {
         vec![menu_bar.into()]
     }
 
+    fn header_end(&self) -> Vec<Element<Self::Message>> {
+        if self.recording().is_none() {
+            return Vec::new();
+        }
+
+        vec![widget::text::body(fl!("recording-indicator"))
+            .class(theme::Text::Color(Color::from_rgb(0.9, 0.1, 0.1)))
+            .into()]
+    }
+
+    /// Opens the ROM passed to a second launch of the app, which hands it over here instead of
+    /// opening another window.
+    fn dbus_activation(
+        &mut self,
+        msg: cosmic::dbus_activation::Message,
+    ) -> Task<cosmic::Action<Self::Message>> {
+        let (rom_path, patch_path) = match msg.msg {
+            cosmic::dbus_activation::Details::ActivateAction { action, args }
+                if action == OPEN_ROM_ACTION =>
+            {
+                let mut args = args.into_iter().map(PathBuf::from);
+                (args.next(), args.next())
+            }
+            cosmic::dbus_activation::Details::Open { url } => (
+                url.into_iter().find_map(|url| url.to_file_path().ok()),
+                None,
+            ),
+            _ => (None, None),
+        };
+
+        match rom_path {
+            Some(rom_path) => self.open_patched_rom(rom_path, patch_path),
+            None => Task::none(),
+        }
+    }
+
+    fn on_close_requested(&self, _id: window::Id) -> Option<Self::Message> {
+        Some(Message::FlushSram)
+    }
+
+    fn dialog(&self) -> Option<Element<Self::Message>> {
+        let prompt = self.archive_prompt.as_ref()?;
+
+        let mut entries = widget::list_column();
+        for (index, entry) in prompt.entries.iter().enumerate() {
+            entries = entries.add(
+                widget::button::custom(widget::text::body(entry.clone()))
+                    .class(theme::Button::MenuItem)
+                    .width(Length::Fill)
+                    .on_press(Message::OpenArchiveEntry(index)),
+            );
+        }
+
+        let dialog = widget::dialog()
+            .title(fl!("choose-rom"))
+            .body(fl!(
+                "archive-has-several-roms",
+                file = file_name_lossy(&prompt.path)
+            ))
+            .control(widget::scrollable(entries))
+            .secondary_action(
+                widget::button::standard(fl!("cancel")).on_press(Message::CancelArchivePrompt),
+            );
+
+        Some(dialog.into())
+    }
+
     fn context_drawer(&self) -> Option<context_drawer::ContextDrawer<Self::Message>> {
         if !self.core.window.show_context {
             return None;
@@ -163,18 +562,29 @@ impl cosmic::Application for AppModel {
                 Message::ToggleContextPage(ContextPage::About),
             )
             .title(fl!("about")),
+            ContextPage::Settings => context_drawer::context_drawer(
+                self.settings(),
+                Message::ToggleContextPage(ContextPage::Settings),
+            )
+            .title(fl!("settings")),
+            ContextPage::RomInfo => context_drawer::context_drawer(
+                self.rom_info(),
+                Message::ToggleContextPage(ContextPage::RomInfo),
+            )
+            .title(fl!("rom-info")),
         })
     }
 
     fn view(&self) -> Element<Self::Message> {
-        widget::responsive(|size| {
+        let content = widget::responsive(|size| {
             let main_element: Element<Self::Message> = if let Some(emulator) = &self.emulator {
                 let image_handle = image::Handle::from_rgba(
-                    SCREEN_WIDTH as u32,
+                    emulator.frame_width() as u32,
                     SCREEN_HEIGHT as u32,
                     emulator.pixels().to_vec(),
                 );
 
+                // The NTSC filter's wider image is squeezed back to the shape of the NES screen.
                 let screen_ratio = SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32;
                 let widget_ratio = size.width / size.height;
 
@@ -189,10 +599,17 @@ impl cosmic::Application for AppModel {
                 widget::image(image_handle)
                     .width(width)
                     .height(height)
+                    .content_fit(ContentFit::Fill)
                     .into()
             } else {
-                // widget::button(fl!("open-rom"), Message::OpenFileDialog)
-                widget::list_column().into()
+                self.library()
+            };
+
+            let main_element = match self.emulator.as_ref().and_then(Emulator::movie_progress) {
+                Some(progress) => {
+                    cosmic::iced::widget::stack![main_element, movie_overlay(progress)].into()
+                }
+                None => main_element,
             };
 
             widget::column()
@@ -205,8 +622,25 @@ impl cosmic::Application for AppModel {
                 .width(Length::Fill)
                 .align_x(Horizontal::Center)
                 .into()
-        })
-        .into()
+        });
+
+        let content: Element<Self::Message> = match &self.drop_target {
+            Some(path) => {
+                let label = if patch::is_patch_file(path) {
+                    fl!("drop-to-patch")
+                } else {
+                    fl!("drop-to-open")
+                };
+                let overlay = widget::container(widget::text::title3(label))
+                    .center(Length::Fill)
+                    .class(theme::Container::Dialog);
+
+                cosmic::iced::widget::stack![content, overlay].into()
+            }
+            None => content.into(),
+        };
+
+        widget::toaster(&self.toasts, content).into()
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
@@ -223,11 +657,12 @@ impl cosmic::Application for AppModel {
                 }),
             event::listen_with(|event, status, _window_id| match event {
                 Event::Keyboard(KeyEvent::KeyPressed {
+                    key,
                     physical_key: Physical::Code(code),
                     modifiers,
                     ..
                 }) => match status {
-                    event::Status::Ignored => Some(Message::KeyDown(modifiers, code)),
+                    event::Status::Ignored => Some(Message::KeyDown(modifiers, key, code)),
                     event::Status::Captured => None,
                 },
                 Event::Keyboard(KeyEvent::KeyReleased {
@@ -238,9 +673,20 @@ impl cosmic::Application for AppModel {
                     event::Status::Ignored => Some(Message::KeyUp(modifiers, code)),
                     event::Status::Captured => None,
                 },
+                Event::Window(window::Event::FileHovered(path)) => Some(Message::FileHovered(path)),
+                Event::Window(window::Event::FilesHoveredLeft) => Some(Message::FilesHoveredLeft),
+                Event::Window(window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
                 _ => None,
             }),
             window::frames().map(|_| Message::Tick),
+            gamepad::subscription().map(|event| match event {
+                GamepadEvent::Connected(id, info) => Message::GamepadConnected(id, info),
+                GamepadEvent::Disconnected(id) => Message::GamepadDisconnected(id),
+                GamepadEvent::Button(id, button, pressed) => {
+                    Message::GamepadButtonChanged(id, button, pressed)
+                }
+                GamepadEvent::Axis(id, axis, value) => Message::GamepadAxisChanged(id, axis, value),
+            }),
         ])
     }
 
@@ -260,7 +706,26 @@ impl cosmic::Application for AppModel {
                 }
             }
             Message::UpdateConfig(config) => {
+                let rom_dir_changed = self.config.rom_dir != config.rom_dir;
+                let audio_changed = self.config.audio_backend != config.audio_backend
+                    || self.config.audio_wav_path != config.audio_wav_path;
+                // Custom palettes are read from disk, so they're only loaded again when the
+                // choice of palette changes.
+                let palette_changed = self.config.palette != config.palette
+                    || self.config.custom_palette != config.custom_palette;
                 self.config = config;
+                if let Some(emulator) = &mut self.emulator {
+                    AppModel::apply_config(&self.config, emulator);
+                    if palette_changed {
+                        emulator.set_palette(load_palette(&self.config));
+                    }
+                }
+                if audio_changed && self.audio_backend_override.is_none() {
+                    self.reopen_audio();
+                }
+                if rom_dir_changed {
+                    return self.scan_library();
+                }
             }
             Message::LaunchUrl(url) => match open::that_detached(&url) {
                 Ok(()) => {}
@@ -276,7 +741,8 @@ impl cosmic::Application for AppModel {
                     self.opening_file = true;
                     return Task::future(async {
                         let file = AsyncFileDialog::new()
-                            .add_filter("NES ROM file", &["nes", "rom"])
+                            .add_filter("NES ROM file", rom::EXTENSIONS)
+                            .add_filter("Compressed NES ROM file", &archive::extensions())
                             .pick_file()
                             .await;
 
@@ -293,33 +759,83 @@ impl cosmic::Application for AppModel {
                 }
 
                 if let Some(rom_path) = path_buf {
-                    if let Ok(rom) = load_rom(&rom_path) {
-                        if let Some(emulator) = &mut self.emulator {
-                            emulator.load_rom(rom, rom_path);
-                        } else {
-                            self.emulator = Some(Emulator::new(rom, rom_path, AppModel::keymap()))
-                        }
+                    return self.open_rom(rom_path);
+                }
+            }
+            Message::OpenPatchDialog => {
+                if let (false, Some(emulator)) = (self.opening_file, &mut self.emulator) {
+                    emulator.pause_emulation();
+                    self.opening_file = true;
+                    return Task::future(async {
+                        let extensions = PatchFormat::ALL.map(PatchFormat::extension);
+                        let file = AsyncFileDialog::new()
+                            .add_filter("ROM patch", &extensions)
+                            .pick_file()
+                            .await;
+
+                        cosmic::Action::App(Message::OpenPatchResult(
+                            file.map(|f| f.path().to_path_buf()),
+                        ))
+                    });
+                }
+            }
+            Message::OpenPatchResult(path_buf) => {
+                self.opening_file = false;
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.resume_emulation();
+                }
+
+                if let Some(patch_path) = path_buf {
+                    return self.apply_patch(patch_path);
+                }
+            }
+            Message::KeyDown(modifiers, key, key_code) => {
+                if let Some((port, button)) = self.rebinding {
+                    if key_code == KeyCode::Escape {
+                        self.rebinding = None;
                     } else {
-                        tracing::error!("error loading rom");
-                        // TODO: Show error message to user.
+                        let mut bindings = self.config.key_bindings();
+                        if keymap::bind(&mut bindings, port, button, key_code) {
+                            self.rebinding = None;
+                            self.set_key_bindings(bindings);
+                        }
+                    }
+                    return Task::none();
+                }
+
+                for (key_bind, action) in &self.key_binds {
+                    if key_bind.matches(modifiers, &key) {
+                        return self.update(action.message());
                     }
                 }
 
-                return self.update_title();
-            }
-            Message::KeyDown(_modifiers, key_code) => {
                 if let Some(emulator) = &mut self.emulator {
-                    emulator.key_down(key_code);
+                    if key_code == REWIND_KEY {
+                        emulator.set_rewinding(true);
+                    } else if key_code == FAST_FORWARD_KEY {
+                        emulator.set_fast_forwarding(true);
+                    } else {
+                        emulator.key_down(key_code);
+                    }
                 }
             }
             Message::KeyUp(_modifiers, key_code) => {
                 if let Some(emulator) = &mut self.emulator {
-                    emulator.key_up(key_code);
+                    if key_code == REWIND_KEY {
+                        emulator.set_rewinding(false);
+                    } else if key_code == FAST_FORWARD_KEY {
+                        emulator.set_fast_forwarding(false);
+                    } else {
+                        emulator.key_up(key_code);
+                    }
                 }
             }
             Message::Tick => {
                 if let Some(emulator) = &mut self.emulator {
                     emulator.tick();
+                    if emulator.take_movie_finished() {
+                        return self.show_toast(fl!("movie-finished"));
+                    }
                 }
             }
             Message::ToggleEmulation => {
@@ -333,75 +849,1522 @@ impl cosmic::Application for AppModel {
                     emulator.reset();
                 }
             }
-        }
-        Task::none()
-    }
-}
+            Message::SaveState => {
+                if let Some(emulator) = &mut self.emulator {
+                    let slot = emulator.save_slot();
+                    return match emulator.save_state() {
+                        Ok(()) => self.show_toast(fl!("state-saved", slot = slot)),
+                        Err(err) => {
+                            tracing::error!("error saving state: {}", err);
+                            self.show_toast(fl!("state-save-error", reason = err.to_string()))
+                        }
+                    };
+                }
+            }
+            Message::LoadState => {
+                if let Some(emulator) = &mut self.emulator {
+                    // Loading a state would desync a running movie from its input.
+                    if emulator.movie_progress().is_some() {
+                        return self.show_toast(fl!("state-load-during-movie"));
+                    }
+                    let slot = emulator.save_slot();
+                    return match emulator.load_state() {
+                        Ok(()) => self.show_toast(fl!("state-loaded", slot = slot)),
+                        Err(err) => {
+                            tracing::error!("error loading state: {}", err);
+                            self.show_toast(fl!("state-load-error", reason = err.to_string()))
+                        }
+                    };
+                }
+            }
+            Message::SelectSaveSlot(slot) => {
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.set_save_slot(slot);
+                }
+            }
+            Message::FlushSram => {
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.flush_sram();
+                    // Finish any recording too, since the app is about to close.
+                    if let Some(Err(err)) = emulator.stop_recording() {
+                        tracing::error!("error finishing recording: {}", err);
+                    }
+                    if let Err(err) = emulator.stop_movie() {
+                        tracing::error!("error saving movie: {}", err);
+                    }
+                }
+            }
+            Message::SetSpeed(speed) => {
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.set_speed(speed);
+                }
+            }
+            Message::FrameAdvance => {
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.frame_advance();
+                }
+            }
+            Message::StepInstruction => {
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.step_instruction();
+                }
+            }
+            Message::RebindButton(port, button) => {
+                self.rebinding = Some((port, button));
+            }
+            Message::ResetKeyBindings => {
+                self.rebinding = None;
+                self.set_key_bindings(Port::ALL.map(keymap::default_key_bindings));
+            }
+            Message::GamepadConnected(id, info) => {
+                tracing::info!("Gamepad connected: {}", info.name);
+                let port = self.free_port_for(&info);
+                self.gamepads.insert(
+                    id,
+                    ConnectedGamepad {
+                        info,
+                        port,
+                        axes: AxisState::default(),
+                    },
+                );
+            }
+            Message::GamepadDisconnected(id) => {
+                if let Some(gamepad) = self.gamepads.remove(&id) {
+                    tracing::info!("Gamepad disconnected: {}", gamepad.info.name);
+                    if let Some(emulator) = &mut self.emulator {
+                        emulator.release_gamepad(id);
+                    }
+                }
+                if matches!(self.gamepad_rebinding, Some((rebind_id, _)) if rebind_id == id) {
+                    self.gamepad_rebinding = None;
+                }
+            }
+            Message::GamepadButtonChanged(id, button, pressed) => {
+                let Some(gamepad) = self.gamepads.get(&id) else {
+                    return Task::none();
+                };
 
-impl AppModel {
-    pub fn about(&self) -> Element<Message> {
-        let cosmic_theme::Spacing { space_xxs, .. } = theme::active().cosmic().spacing;
+                if let Some((rebind_id, nes_button)) = self.gamepad_rebinding {
+                    if rebind_id == id && pressed {
+                        let uuid = gamepad.info.uuid.clone();
+                        let mut mapping = self.gamepad_mapping(&uuid);
+                        mapping.retain(|_, mapped| *mapped != button);
+                        mapping.insert(nes_button, button);
+                        self.gamepad_rebinding = None;
+                        self.set_gamepad_mapping(uuid, mapping);
+                    }
+                    return Task::none();
+                }
 
-        let icon = widget::svg(widget::svg::Handle::from_memory(APP_ICON));
+                let nes_button =
+                    gamepad::mapped_button(&self.gamepad_mapping(&gamepad.info.uuid), button);
+                if let (Some(port), Some(nes_button), Some(emulator)) =
+                    (gamepad.port, nes_button, &mut self.emulator)
+                {
+                    emulator.set_button_pressed(
+                        InputSource::GamepadButtons(id),
+                        port,
+                        nes_button.button(),
+                        pressed,
+                    );
+                }
+            }
+            Message::GamepadAxisChanged(id, axis, value) => {
+                let Some(gamepad) = self.gamepads.get_mut(&id) else {
+                    return Task::none();
+                };
+                let Some(change) = gamepad.axes.update(axis, value) else {
+                    return Task::none();
+                };
 
-        let title = widget::text::title3(fl!("app-title"));
+                if let (Some(port), Some(emulator)) = (gamepad.port, &mut self.emulator) {
+                    let source = InputSource::GamepadAxis(id, axis);
+                    if let Some(released) = change.released {
+                        emulator.set_button_pressed(source, port, released.button(), false);
+                    }
+                    if let Some(pressed) = change.pressed {
+                        emulator.set_button_pressed(source, port, pressed.button(), true);
+                    }
+                }
+            }
+            Message::RebindGamepadButton(id, nes_button) => {
+                self.gamepad_rebinding = Some((id, nes_button));
+            }
+            Message::CloseToast(id) => {
+                self.toasts.remove(id);
+            }
+            Message::ScanLibrary => {
+                return self.scan_library();
+            }
+            Message::LibraryScanned(entries) => {
+                self.scanning_library = false;
+                self.library = entries;
+            }
+            Message::LibraryFilterChanged(filter) => {
+                self.library_filter = filter;
+            }
+            Message::OpenLibraryEntry(rom_path) => {
+                return self.open_rom(rom_path);
+            }
+            Message::ChooseRomDir => {
+                return Task::future(async {
+                    let folder = AsyncFileDialog::new().pick_folder().await;
 
-        let hash = env!("VERGEN_GIT_SHA");
-        let short_hash: String = hash.chars().take(7).collect();
-        let date = env!("VERGEN_GIT_COMMIT_DATE");
+                    cosmic::Action::App(Message::RomDirChosen(
+                        folder.map(|f| f.path().to_path_buf()),
+                    ))
+                });
+            }
+            Message::RomDirChosen(rom_dir) => {
+                if let Some(rom_dir) = rom_dir {
+                    self.set_rom_dir(rom_dir);
+                    return self.scan_library();
+                }
+            }
+            Message::OpenRecentRom(index) => {
+                let Some(rom_path) = self.config.recent_roms.get(index).cloned() else {
+                    return Task::none();
+                };
 
-        let link = widget::button::link(REPOSITORY)
-            .on_press(Message::OpenRepositoryUrl)
-            .padding(0);
+                if !rom_path.exists() {
+                    self.prune_recent_roms();
+                    return self.show_toast(fl!(
+                        "recent-rom-missing",
+                        file = rom_path.display().to_string()
+                    ));
+                }
 
-        widget::column()
-            .push(icon)
-            .push(title)
-            .push(link)
-            .push(
-                widget::button::link(fl!(
-                    "git-description",
-                    hash = short_hash.as_str(),
-                    date = date
-                ))
-                .on_press(Message::LaunchUrl(format!("{REPOSITORY}/commits/{hash}")))
-                .padding(0),
-            )
-            .align_x(Alignment::Center)
-            .spacing(space_xxs)
-            .into()
-    }
+                return self.open_rom(rom_path);
+            }
+            Message::ClearRecentRoms => {
+                self.set_recent_roms(Vec::new());
+            }
+            Message::FileHovered(path) => {
+                self.drop_target = Some(path);
+            }
+            Message::FilesHoveredLeft => {
+                self.drop_target = None;
+            }
+            Message::FileDropped(path) => {
+                self.drop_target = None;
+                if patch::is_patch_file(&path) {
+                    return self.apply_patch(path);
+                } else if library::is_rom_file(&path) {
+                    return self.open_rom(path);
+                }
+                return self.show_toast(fl!("unsupported-file", file = file_name_lossy(&path)));
+            }
+            Message::OpenArchiveEntry(index) => {
+                if let Some(prompt) = self.archive_prompt.take() {
+                    if let Some(entry) = prompt.entries.into_iter().nth(index) {
+                        return self.load_game(prompt.path, Some(entry), prompt.patch);
+                    }
+                }
+            }
+            Message::CancelArchivePrompt => {
+                self.archive_prompt = None;
+            }
+            Message::AssignGamepadPort(id, port) => {
+                // A port can only be driven by one controller at a time.
+                if port.is_some() {
+                    for (other_id, gamepad) in self.gamepads.iter_mut() {
+                        if gamepad.port == port {
+                            gamepad.port = None;
+                            gamepad.axes = AxisState::default();
+                            if let Some(emulator) = &mut self.emulator {
+                                emulator.release_gamepad(*other_id);
+                            }
+                        }
+                    }
+                }
 
-    pub fn update_title(&mut self) -> Task<cosmic::Action<Message>> {
-        let mut window_title = fl!("app-title");
+                if let Some(gamepad) = self.gamepads.get_mut(&id) {
+                    gamepad.port = port;
+                    gamepad.axes = AxisState::default();
+                    // Buttons held on the old port would otherwise stay pressed there.
+                    if let Some(emulator) = &mut self.emulator {
+                        emulator.release_gamepad(id);
+                    }
+                    let mut gamepad_ports = self.config.gamepad_ports.clone();
+                    gamepad_ports.insert(gamepad.info.uuid.clone(), port);
+                    self.set_gamepad_ports(gamepad_ports);
+                }
+            }
+            Message::SetAudioBackend(backend) => {
+                // Choosing a backend in the settings replaces the one from the command line.
+                self.audio_backend_override = None;
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) = self.config.set_audio_backend(config_handler, backend) {
+                            tracing::error!("failed to save audio backend: {}", err);
+                        }
+                    }
+                    None => self.config.audio_backend = backend,
+                }
+                self.reopen_audio();
+            }
+            Message::TakeScreenshot => {
+                let Some(emulator) = &self.emulator else {
+                    return Task::none();
+                };
 
-        if let Some(Some(rom_name)) = self
-            .emulator
-            .as_ref()
-            .map(|e| e.rom_path().file_name().map(|f| f.to_string_lossy()))
-        {
-            window_title.push_str(" — ");
-            window_title.push_str(&rom_name);
-        }
+                let dir = self
+                    .config
+                    .screenshot_dir
+                    .clone()
+                    .unwrap_or_else(screenshot::default_dir);
+                let result = screenshot::save(
+                    emulator.pixels(),
+                    self.config.screenshot_scale,
+                    &dir,
+                    emulator.rom_path(),
+                );
+                return match result {
+                    Ok(path) => {
+                        self.show_toast(fl!("screenshot-saved", path = path.display().to_string()))
+                    }
+                    Err(err) => {
+                        tracing::error!("error saving screenshot: {}", err);
+                        self.show_toast(fl!("screenshot-error", reason = err.to_string()))
+                    }
+                };
+            }
+            Message::ToggleRecording(kind) => {
+                let Some(emulator) = &mut self.emulator else {
+                    return Task::none();
+                };
 
-        if let Some(id) = self.core.main_window_id() {
-            self.set_window_title(window_title, id)
-        } else {
-            Task::none()
-        }
-    }
+                // Only one recording runs at a time, so starting one stops the other.
+                let was_recording = emulator.recording();
+                let mut toasts = Vec::new();
+                match emulator.stop_recording() {
+                    Some(Ok(path)) => {
+                        toasts.push(fl!("recording-saved", path = path.display().to_string()))
+                    }
+                    Some(Err(err)) => {
+                        tracing::error!("error finishing recording: {}", err);
+                        toasts.push(fl!("recording-error", reason = err.to_string()));
+                    }
+                    None => {}
+                }
+
+                if was_recording != Some(kind) {
+                    let dir = self
+                        .config
+                        .recording_dir
+                        .clone()
+                        .unwrap_or_else(recording::default_dir);
+                    if let Err(err) = emulator.start_recording(&dir, kind) {
+                        tracing::error!("error starting recording: {}", err);
+                        toasts.push(fl!("recording-error", reason = err.to_string()));
+                    }
+                }
+
+                let tasks: Vec<_> = toasts
+                    .into_iter()
+                    .map(|toast| self.show_toast(toast))
+                    .collect();
+                return Task::batch(tasks);
+            }
+            Message::RecordMovie(start) => {
+                if let (false, Some(emulator)) = (self.opening_file, &mut self.emulator) {
+                    emulator.pause_emulation();
+                    self.opening_file = true;
+                    let file_name = emulator
+                        .rom_path()
+                        .file_stem()
+                        .map(|stem| format!("{}.fm2", stem.to_string_lossy()))
+                        .unwrap_or_default();
+                    return Task::future(async move {
+                        let file = AsyncFileDialog::new()
+                            .add_filter("FM2 movie", &["fm2"])
+                            .set_file_name(file_name)
+                            .save_file()
+                            .await;
+
+                        cosmic::Action::App(Message::RecordMoviePathChosen(
+                            start,
+                            file.map(|f| f.path().to_path_buf()),
+                        ))
+                    });
+                }
+            }
+            Message::RecordMoviePathChosen(start, path) => {
+                self.opening_file = false;
+                let Some(emulator) = &mut self.emulator else {
+                    return Task::none();
+                };
+                emulator.resume_emulation();
+
+                if let Some(path) = path {
+                    if let Err(err) = emulator.record_movie(path, start) {
+                        tracing::error!("error starting movie: {}", err);
+                        return self.show_toast(fl!("movie-error", reason = err.to_string()));
+                    }
+                }
+            }
+            Message::PlayMovie => {
+                if let (false, Some(emulator)) = (self.opening_file, &mut self.emulator) {
+                    emulator.pause_emulation();
+                    self.opening_file = true;
+                    return Task::future(async {
+                        let file = AsyncFileDialog::new()
+                            .add_filter("FM2 movie", &["fm2"])
+                            .pick_file()
+                            .await;
+
+                        cosmic::Action::App(Message::PlayMovieResult(
+                            file.map(|f| f.path().to_path_buf()),
+                        ))
+                    });
+                }
+            }
+            Message::PlayMovieResult(path) => {
+                self.opening_file = false;
+                let Some(emulator) = &mut self.emulator else {
+                    return Task::none();
+                };
+                emulator.resume_emulation();
+
+                let Some(path) = path else {
+                    return Task::none();
+                };
+                let result = Movie::load(&path).map_err(Into::into).and_then(|movie| {
+                    let matches_rom = movie.rom_md5 == emulator.rom_info().checksums.md5;
+                    emulator.play_movie(movie).map(|()| matches_rom)
+                });
+                return match result {
+                    Ok(true) => Task::none(),
+                    // The movie may still play fine on a differently dumped or patched ROM.
+                    Ok(false) => self.show_toast(fl!("movie-rom-mismatch")),
+                    Err(err) => {
+                        tracing::error!("error playing movie {}: {}", path.display(), err);
+                        self.show_toast(fl!("movie-error", reason = err.to_string()))
+                    }
+                };
+            }
+            Message::StopMovie => {
+                if let Some(emulator) = &mut self.emulator {
+                    return match emulator.stop_movie() {
+                        Ok(Some(path)) => {
+                            self.show_toast(fl!("movie-saved", path = path.display().to_string()))
+                        }
+                        Ok(None) => Task::none(),
+                        Err(err) => {
+                            tracing::error!("error saving movie: {}", err);
+                            self.show_toast(fl!("movie-error", reason = err.to_string()))
+                        }
+                    };
+                }
+            }
+            Message::ChooseRecordingDir => {
+                return Task::future(async {
+                    let folder = AsyncFileDialog::new().pick_folder().await;
+
+                    cosmic::Action::App(Message::RecordingDirChosen(
+                        folder.map(|f| f.path().to_path_buf()),
+                    ))
+                });
+            }
+            Message::RecordingDirChosen(dir) => {
+                if let Some(dir) = dir {
+                    match &self.config_handler {
+                        Some(config_handler) => {
+                            if let Err(err) =
+                                self.config.set_recording_dir(config_handler, Some(dir))
+                            {
+                                tracing::error!("failed to save recording directory: {}", err);
+                            }
+                        }
+                        None => self.config.recording_dir = Some(dir),
+                    }
+                }
+            }
+            Message::ChooseScreenshotDir => {
+                return Task::future(async {
+                    let folder = AsyncFileDialog::new().pick_folder().await;
+
+                    cosmic::Action::App(Message::ScreenshotDirChosen(
+                        folder.map(|f| f.path().to_path_buf()),
+                    ))
+                });
+            }
+            Message::ScreenshotDirChosen(dir) => {
+                if let Some(dir) = dir {
+                    match &self.config_handler {
+                        Some(config_handler) => {
+                            if let Err(err) =
+                                self.config.set_screenshot_dir(config_handler, Some(dir))
+                            {
+                                tracing::error!("failed to save screenshot directory: {}", err);
+                            }
+                        }
+                        None => self.config.screenshot_dir = Some(dir),
+                    }
+                }
+            }
+            Message::SetScreenshotScale(scale) => match &self.config_handler {
+                Some(config_handler) => {
+                    if let Err(err) = self.config.set_screenshot_scale(config_handler, scale) {
+                        tracing::error!("failed to save screenshot size: {}", err);
+                    }
+                }
+                None => self.config.screenshot_scale = scale,
+            },
+            Message::SetRewindInterval(frames) => {
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) = self
+                            .config
+                            .set_rewind_interval_frames(config_handler, frames)
+                        {
+                            tracing::error!("failed to save rewind interval: {}", err);
+                        }
+                    }
+                    None => self.config.rewind_interval_frames = frames,
+                }
+                if let Some(emulator) = &mut self.emulator {
+                    AppModel::apply_config(&self.config, emulator);
+                }
+            }
+            Message::SetRewindBuffer(mb) => {
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) = self.config.set_rewind_buffer_mb(config_handler, mb) {
+                            tracing::error!("failed to save rewind buffer size: {}", err);
+                        }
+                    }
+                    None => self.config.rewind_buffer_mb = mb,
+                }
+                if let Some(emulator) = &mut self.emulator {
+                    AppModel::apply_config(&self.config, emulator);
+                }
+            }
+            Message::SetPalette(choice) => {
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) = self.config.set_palette(config_handler, choice) {
+                            tracing::error!("failed to save palette: {}", err);
+                        }
+                    }
+                    None => self.config.palette = choice,
+                }
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.set_palette(load_palette(&self.config));
+                }
+            }
+            Message::ChoosePaletteFile => {
+                return Task::future(async {
+                    let file = AsyncFileDialog::new()
+                        .add_filter("NES palette", &["pal"])
+                        .pick_file()
+                        .await;
+
+                    cosmic::Action::App(Message::PaletteFileChosen(
+                        file.map(|f| f.path().to_path_buf()),
+                    ))
+                });
+            }
+            Message::PaletteFileChosen(path) => {
+                let Some(path) = path else {
+                    return Task::none();
+                };
+                // Check the file before saving it, so a bad one doesn't end up in the config.
+                let palette = match Palette::load(&path) {
+                    Ok(palette) => palette,
+                    Err(err) => {
+                        tracing::error!("error loading palette {}: {}", path.display(), err);
+                        return self.show_toast(fl!("palette-error", reason = err.to_string()));
+                    }
+                };
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) = self
+                            .config
+                            .set_custom_palette(config_handler, Some(path))
+                            .and_then(|_| {
+                                self.config
+                                    .set_palette(config_handler, PaletteChoice::Custom)
+                            })
+                        {
+                            tracing::error!("failed to save palette: {}", err);
+                        }
+                    }
+                    None => {
+                        self.config.custom_palette = Some(path);
+                        self.config.palette = PaletteChoice::Custom;
+                    }
+                }
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.set_palette(palette);
+                }
+            }
+            Message::SetNtscFilter(preset) => {
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) = self.config.set_ntsc_filter(config_handler, preset) {
+                            tracing::error!("failed to save NTSC filter: {}", err);
+                        }
+                    }
+                    None => self.config.ntsc_filter = preset,
+                }
+                if let Some(emulator) = &mut self.emulator {
+                    emulator.set_ntsc_filter(preset);
+                }
+            }
+        }
+        Task::none()
+    }
+}
+
+impl AppModel {
+    pub fn about(&self) -> Element<Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = theme::active().cosmic().spacing;
+
+        let icon = widget::svg(widget::svg::Handle::from_memory(APP_ICON));
+
+        let title = widget::text::title3(fl!("app-title"));
+
+        let hash = env!("VERGEN_GIT_SHA");
+        let short_hash: String = hash.chars().take(7).collect();
+        let date = env!("VERGEN_GIT_COMMIT_DATE");
+
+        let link = widget::button::link(REPOSITORY)
+            .on_press(Message::OpenRepositoryUrl)
+            .padding(0);
+
+        widget::column()
+            .push(icon)
+            .push(title)
+            .push(link)
+            .push(
+                widget::button::link(fl!(
+                    "git-description",
+                    hash = short_hash.as_str(),
+                    date = date
+                ))
+                .on_press(Message::LaunchUrl(format!("{REPOSITORY}/commits/{hash}")))
+                .padding(0),
+            )
+            .align_x(Alignment::Center)
+            .spacing(space_xxs)
+            .into()
+    }
+
+    /// Details of the loaded cartridge.
+    pub fn rom_info(&self) -> Element<Message> {
+        let Some(emulator) = &self.emulator else {
+            return widget::text::body(fl!("no-game-loaded")).into();
+        };
+
+        let info = emulator.rom_info();
+        let header = &info.header;
+
+        let mut path = emulator.rom_path().display().to_string();
+        if let Some(entry) = &self.rom_entry {
+            path.push_str(" → ");
+            path.push_str(entry);
+        }
+        let mapper = |header: &rom::RomHeader| match rom::mapper_name(header.mapper) {
+            Some(name) => format!("{} ({name})", header.mapper),
+            None => header.mapper.to_string(),
+        };
+        let mirroring = |header: &rom::RomHeader| match header.mirroring {
+            Mirroring::Horizontal => fl!("mirroring-horizontal"),
+            Mirroring::Vertical => fl!("mirroring-vertical"),
+            Mirroring::FourScreen => fl!("mirroring-four-screen"),
+        };
+        let region = |header: &rom::RomHeader| {
+            match header.region {
+                Region::Ntsc => "NTSC",
+                Region::Pal => "PAL",
+                Region::Multi => "NTSC/PAL",
+                Region::Dendy => "Dendy",
+            }
+            .to_string()
+        };
+        let battery = |header: &rom::RomHeader| {
+            if header.has_battery {
+                fl!("yes")
+            } else {
+                fl!("no")
+            }
+        };
+        // Fields the ROM database corrected show what the file's header said alongside.
+        let field = |describe: &dyn Fn(&rom::RomHeader) -> String| {
+            let value = describe(header);
+            match &info.original_header {
+                Some(original) if describe(original) != value => fl!(
+                    "corrected-value",
+                    value = value,
+                    original = describe(original)
+                ),
+                _ => value,
+            }
+        };
+        let format = if info.original_header.as_ref().unwrap_or(header).nes2 {
+            "NES 2.0"
+        } else {
+            "iNES"
+        };
+        let header_format = match info.original_header {
+            Some(_) => fl!("header-corrected", format = format),
+            None => format.to_string(),
+        };
+
+        let mut file_section = widget::settings::section()
+            .title(fl!("file"))
+            .add(info_item(fl!("path"), path))
+            .add(info_item("CRC32", format!("{:08X}", info.checksums.crc32)))
+            .add(info_item("SHA-1", info.checksums.sha1.clone()));
+        if let Some(patch) = &info.patch {
+            file_section = file_section.add(info_item(fl!("patch"), patch.display().to_string()));
+        }
+
+        let cartridge_section = widget::settings::section()
+            .title(fl!("cartridge"))
+            .add(info_item(fl!("mapper"), field(&mapper)))
+            .add(info_item(
+                fl!("prg-rom"),
+                fl!("size-kib", size = header.prg_rom_len / 1024),
+            ))
+            .add(info_item(
+                fl!("chr-rom"),
+                if header.chr_rom_len == 0 {
+                    fl!("chr-ram")
+                } else {
+                    fl!("size-kib", size = header.chr_rom_len / 1024)
+                },
+            ))
+            .add(info_item(fl!("mirroring"), field(&mirroring)))
+            .add(info_item(fl!("battery"), field(&battery)))
+            .add(info_item(fl!("region"), field(&region)))
+            .add(info_item(fl!("header-format"), header_format));
+
+        let database_section =
+            widget::settings::section()
+                .title(fl!("rom-database"))
+                .add(info_item(
+                    fl!("database-match"),
+                    match &info.game {
+                        Some(game) => game.title.clone(),
+                        None => fl!("no-match"),
+                    },
+                ));
+
+        widget::settings::view_column(vec![
+            file_section.into(),
+            cartridge_section.into(),
+            database_section.into(),
+        ])
+        .into()
+    }
+
+    pub fn settings(&self) -> Element<Message> {
+        let bindings = self.config.key_bindings();
+        let mut sections: Vec<Element<Message>> = Port::ALL
+            .into_iter()
+            .map(|port| {
+                let title = match port {
+                    Port::One => fl!("player-1-controls"),
+                    Port::Two => fl!("player-2-controls"),
+                };
+                let mut section = widget::settings::section().title(title);
+
+                for button in NesButton::ALL {
+                    let key_label = if self.rebinding == Some((port, button)) {
+                        fl!("press-a-key")
+                    } else {
+                        bindings[port.index()]
+                            .get(&button)
+                            .map(|name| keymap::key_label(name).to_string())
+                            .unwrap_or_else(|| fl!("unbound"))
+                    };
+
+                    section = section.add(widget::settings::item(
+                        nes_button_label(button),
+                        widget::button::standard(key_label)
+                            .on_press(Message::RebindButton(port, button)),
+                    ));
+                }
+
+                section.into()
+            })
+            .collect();
+
+        sections.push(
+            widget::button::standard(fl!("reset-to-defaults"))
+                .on_press(Message::ResetKeyBindings)
+                .into(),
+        );
+
+        let audio_backend = self.audio_backend();
+        let selected_backend = AudioBackend::ALL
+            .iter()
+            .position(|backend| *backend == audio_backend);
+        sections.push(
+            widget::settings::section()
+                .title(fl!("audio"))
+                .add(widget::settings::item(
+                    fl!("audio-output"),
+                    widget::dropdown(&self.audio_backend_options, selected_backend, |index| {
+                        Message::SetAudioBackend(AudioBackend::ALL[index])
+                    }),
+                ))
+                .into(),
+        );
+
+        let selected_interval = rewind::INTERVALS
+            .iter()
+            .position(|frames| *frames == self.config.rewind_interval_frames);
+        let selected_buffer = rewind::BUFFER_SIZES_MB
+            .iter()
+            .position(|mb| *mb == self.config.rewind_buffer_mb);
+        sections.push(
+            widget::settings::section()
+                .title(fl!("rewind"))
+                .add(widget::settings::item(
+                    fl!("rewind-interval"),
+                    widget::dropdown(&self.rewind_interval_options, selected_interval, |index| {
+                        Message::SetRewindInterval(rewind::INTERVALS[index])
+                    }),
+                ))
+                .add(widget::settings::item(
+                    fl!("rewind-buffer"),
+                    widget::dropdown(&self.rewind_buffer_options, selected_buffer, |index| {
+                        Message::SetRewindBuffer(rewind::BUFFER_SIZES_MB[index])
+                    }),
+                ))
+                .into(),
+        );
+
+        let screenshot_dir = self
+            .config
+            .screenshot_dir
+            .clone()
+            .unwrap_or_else(screenshot::default_dir);
+        let selected_scale = screenshot::SCALES
+            .iter()
+            .position(|scale| *scale == self.config.screenshot_scale);
+        sections.push(
+            widget::settings::section()
+                .title(fl!("screenshots"))
+                .add(widget::settings::item(
+                    fl!("screenshot-folder"),
+                    widget::button::standard(file_name_lossy(&screenshot_dir))
+                        .on_press(Message::ChooseScreenshotDir),
+                ))
+                .add(widget::settings::item(
+                    fl!("screenshot-size"),
+                    widget::dropdown(&self.screenshot_scale_options, selected_scale, |index| {
+                        Message::SetScreenshotScale(screenshot::SCALES[index])
+                    }),
+                ))
+                .into(),
+        );
+
+        let recording_dir = self
+            .config
+            .recording_dir
+            .clone()
+            .unwrap_or_else(recording::default_dir);
+        sections.push(
+            widget::settings::section()
+                .title(fl!("recordings"))
+                .add(widget::settings::item(
+                    fl!("recording-folder"),
+                    widget::button::standard(file_name_lossy(&recording_dir))
+                        .on_press(Message::ChooseRecordingDir),
+                ))
+                .into(),
+        );
+
+        for (&id, gamepad) in &self.gamepads {
+            let mapping = self.gamepad_mapping(&gamepad.info.uuid);
+            let selected_port = match gamepad.port {
+                None => 0,
+                Some(Port::One) => 1,
+                Some(Port::Two) => 2,
+            };
+
+            let mut section = widget::settings::section()
+                .title(gamepad.info.name.clone())
+                .add(widget::settings::item(
+                    fl!("port"),
+                    widget::dropdown(&self.port_options, Some(selected_port), move |index| {
+                        // The first option leaves the controller unassigned.
+                        let port = index.checked_sub(1).and_then(|index| Port::ALL.get(index));
+                        Message::AssignGamepadPort(id, port.copied())
+                    }),
+                ));
+
+            for nes_button in NesButton::ALL {
+                let button_label = if self.gamepad_rebinding == Some((id, nes_button)) {
+                    fl!("press-a-button")
+                } else {
+                    mapping
+                        .get(&nes_button)
+                        .map(|button| format!("{button:?}"))
+                        .unwrap_or_else(|| fl!("unbound"))
+                };
+
+                section = section.add(widget::settings::item(
+                    nes_button_label(nes_button),
+                    widget::button::standard(button_label)
+                        .on_press(Message::RebindGamepadButton(id, nes_button)),
+                ));
+            }
+
+            sections.push(section.into());
+        }
+
+        widget::settings::view_column(sections).into()
+    }
+
+    /// Lists the games found in the ROM directory, shown when no game is running.
+    pub fn library(&self) -> Element<Message> {
+        let cosmic_theme::Spacing {
+            space_xxs, space_s, ..
+        } = theme::active().cosmic().spacing;
+
+        let toolbar = widget::row()
+            .push(
+                widget::search_input(fl!("search-library"), &self.library_filter)
+                    .on_input(Message::LibraryFilterChanged)
+                    .on_clear(Message::LibraryFilterChanged(String::new()))
+                    .width(Length::Fill),
+            )
+            .push(
+                widget::button::standard(fl!("choose-rom-folder")).on_press(Message::ChooseRomDir),
+            )
+            .push(
+                widget::button::standard(fl!("rescan")).on_press_maybe(
+                    (self.config.rom_dir.is_some() && !self.scanning_library)
+                        .then_some(Message::ScanLibrary),
+                ),
+            )
+            .spacing(space_xxs);
+
+        let body: Element<Message> = if self.config.rom_dir.is_none() {
+            widget::text::body(fl!("no-rom-folder")).into()
+        } else if self.scanning_library && self.library.is_empty() {
+            widget::text::body(fl!("scanning-library")).into()
+        } else if self.library.is_empty() {
+            widget::text::body(fl!("library-empty")).into()
+        } else {
+            let mut list = widget::list_column();
+            for entry in self
+                .library
+                .iter()
+                .filter(|entry| entry.matches(&self.library_filter))
+            {
+                let mapper = entry
+                    .mapper
+                    .map(|mapper| mapper.to_string())
+                    .unwrap_or_else(|| "?".to_string());
+                let last_played = self
+                    .config
+                    .last_played
+                    .get(&entry.path)
+                    .and_then(|secs| DateTime::from_timestamp(*secs as i64, 0))
+                    .map(|time| time.with_timezone(&Local).format("%x").to_string())
+                    .unwrap_or_else(|| fl!("never-played"));
+                let details = fl!(
+                    "library-entry-details",
+                    mapper = mapper,
+                    size = (entry.size / 1024).to_string(),
+                    played = last_played
+                );
+
+                list = list.add(
+                    widget::button::custom(
+                        widget::column()
+                            .push(widget::text::body(entry.title.clone()))
+                            .push(widget::text::caption(details)),
+                    )
+                    .class(theme::Button::MenuItem)
+                    .width(Length::Fill)
+                    .on_press(Message::OpenLibraryEntry(entry.path.clone())),
+                );
+            }
+            widget::scrollable(list).into()
+        };
+
+        widget::column()
+            .push(toolbar)
+            .push(body)
+            .spacing(space_s)
+            .padding(space_s)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .into()
+    }
+
+    /// Rescans the ROM directory on a background thread.
+    fn scan_library(&mut self) -> Task<cosmic::Action<Message>> {
+        let Some(rom_dir) = self.config.rom_dir.clone() else {
+            self.library.clear();
+            return Task::none();
+        };
+
+        self.scanning_library = true;
+        Task::future(async move {
+            let entries = tokio::task::spawn_blocking(move || library::scan(&rom_dir))
+                .await
+                .unwrap_or_default();
+
+            cosmic::Action::App(Message::LibraryScanned(entries))
+        })
+    }
+
+    fn set_rom_dir(&mut self, rom_dir: PathBuf) {
+        match &self.config_handler {
+            Some(config_handler) => {
+                if let Err(err) = self.config.set_rom_dir(config_handler, Some(rom_dir)) {
+                    tracing::error!("failed to save ROM directory: {}", err);
+                }
+            }
+            None => self.config.rom_dir = Some(rom_dir),
+        }
+    }
+
+    /// Records that a ROM was just opened, for the library's last played column and the recent
+    /// ROMs menu.
+    fn record_opened(&mut self, rom_path: PathBuf) {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let mut last_played = self.config.last_played.clone();
+        last_played.insert(rom_path.clone(), now);
+
+        let mut recent_roms = self.config.recent_roms.clone();
+        recent_roms.retain(|path| *path != rom_path);
+        recent_roms.insert(0, rom_path);
+        recent_roms.truncate(MAX_RECENT_ROMS);
+        self.set_recent_roms(recent_roms);
+
+        match &self.config_handler {
+            Some(config_handler) => {
+                if let Err(err) = self.config.set_last_played(config_handler, last_played) {
+                    tracing::error!("failed to save last played times: {}", err);
+                }
+            }
+            None => self.config.last_played = last_played,
+        }
+    }
+
+    fn palette_items(&self) -> Vec<menu::Item<MenuAction, String>> {
+        let mut items: Vec<_> = PaletteChoice::BUILT_IN
+            .into_iter()
+            .map(|choice| {
+                menu::Item::CheckBox(
+                    palette_label(choice),
+                    None,
+                    self.config.palette == choice,
+                    MenuAction::SetPalette(choice),
+                )
+            })
+            .collect();
+
+        items.push(menu::Item::Divider);
+        if let Some(path) = &self.config.custom_palette {
+            items.push(menu::Item::CheckBox(
+                file_name_lossy(path),
+                None,
+                self.config.palette == PaletteChoice::Custom,
+                MenuAction::SetPalette(PaletteChoice::Custom),
+            ));
+        }
+        items.push(menu::Item::Button(
+            fl!("palette-load"),
+            None,
+            MenuAction::ChoosePaletteFile,
+        ));
+
+        // The NTSC filter gets its colours from decoding the signal, so the palette isn't used
+        // while it's on.
+        if self.config.ntsc_filter.is_some() {
+            items = items
+                .into_iter()
+                .map(|item| match item {
+                    menu::Item::CheckBox(label, icon, _, action) => {
+                        menu::Item::ButtonDisabled(label, icon, action)
+                    }
+                    menu::Item::Button(label, icon, action) => {
+                        menu::Item::ButtonDisabled(label, icon, action)
+                    }
+                    item => item,
+                })
+                .collect();
+        }
+        items
+    }
+
+    fn ntsc_filter_items(&self) -> Vec<menu::Item<MenuAction, String>> {
+        let mut items = vec![
+            menu::Item::CheckBox(
+                fl!("ntsc-off"),
+                None,
+                self.config.ntsc_filter.is_none(),
+                MenuAction::SetNtscFilter(None),
+            ),
+            menu::Item::Divider,
+        ];
+        items.extend(NtscPreset::ALL.into_iter().map(|preset| {
+            menu::Item::CheckBox(
+                ntsc_preset_label(preset),
+                None,
+                self.config.ntsc_filter == Some(preset),
+                MenuAction::SetNtscFilter(Some(preset)),
+            )
+        }));
+        items
+    }
+
+    fn recent_rom_items(&self) -> Vec<menu::Item<MenuAction, String>> {
+        if self.config.recent_roms.is_empty() {
+            return vec![menu::Item::ButtonDisabled(
+                fl!("no-recent-roms"),
+                None,
+                MenuAction::ClearRecentRoms,
+            )];
+        }
+
+        let mut items: Vec<_> = self
+            .config
+            .recent_roms
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                menu::Item::Button(
+                    file_name_lossy(path),
+                    None,
+                    MenuAction::OpenRecentRom(index),
+                )
+            })
+            .collect();
+
+        items.push(menu::Item::Divider);
+        items.push(menu::Item::Button(
+            fl!("clear-recent"),
+            None,
+            MenuAction::ClearRecentRoms,
+        ));
+        items
+    }
+
+    /// Drops recent ROMs whose files have been moved or deleted.
+    fn prune_recent_roms(&mut self) {
+        let mut recent_roms = self.config.recent_roms.clone();
+        recent_roms.retain(|path| path.exists());
+        if recent_roms.len() != self.config.recent_roms.len() {
+            self.set_recent_roms(recent_roms);
+        }
+    }
+
+    fn set_recent_roms(&mut self, recent_roms: Vec<PathBuf>) {
+        match &self.config_handler {
+            Some(config_handler) => {
+                if let Err(err) = self.config.set_recent_roms(config_handler, recent_roms) {
+                    tracing::error!("failed to save recent ROMs: {}", err);
+                }
+            }
+            None => self.config.recent_roms = recent_roms,
+        }
+    }
+
+    fn set_key_bindings(&mut self, bindings: [KeyBindings; 2]) {
+        let [bindings_1, bindings_2] = bindings;
+        match &self.config_handler {
+            Some(config_handler) => {
+                let result = self
+                    .config
+                    .set_keyboard_bindings(config_handler, bindings_1)
+                    .and_then(|_| {
+                        self.config
+                            .set_keyboard_bindings_2(config_handler, bindings_2)
+                    });
+                if let Err(err) = result {
+                    tracing::error!("failed to save key bindings: {}", err);
+                }
+            }
+            None => {
+                self.config.keyboard_bindings = bindings_1;
+                self.config.keyboard_bindings_2 = bindings_2;
+            }
+        }
+
+        if let Some(emulator) = &mut self.emulator {
+            emulator.set_keymap(keymap::keymap(&self.config.key_bindings()));
+        }
+    }
+
+    fn gamepad_mapping(&self, uuid: &str) -> GamepadMapping {
+        self.config
+            .gamepad_mappings
+            .get(uuid)
+            .cloned()
+            .unwrap_or_else(gamepad::default_mapping)
+    }
+
+    fn set_gamepad_mapping(&mut self, uuid: String, mapping: GamepadMapping) {
+        let mut gamepad_mappings = self.config.gamepad_mappings.clone();
+        gamepad_mappings.insert(uuid, mapping);
+
+        match &self.config_handler {
+            Some(config_handler) => {
+                if let Err(err) = self
+                    .config
+                    .set_gamepad_mappings(config_handler, gamepad_mappings)
+                {
+                    tracing::error!("failed to save gamepad mappings: {}", err);
+                }
+            }
+            None => self.config.gamepad_mappings = gamepad_mappings,
+        }
+    }
+
+    fn set_gamepad_ports(&mut self, gamepad_ports: BTreeMap<String, Option<Port>>) {
+        match &self.config_handler {
+            Some(config_handler) => {
+                if let Err(err) = self.config.set_gamepad_ports(config_handler, gamepad_ports) {
+                    tracing::error!("failed to save gamepad ports: {}", err);
+                }
+            }
+            None => self.config.gamepad_ports = gamepad_ports,
+        }
+    }
+
+    /// Picks the port for a newly connected controller, preferring the one it was assigned to
+    /// before, and otherwise the first port no other controller is using.
+    fn free_port_for(&self, info: &GamepadInfo) -> Option<Port> {
+        let taken: Vec<Port> = self
+            .gamepads
+            .values()
+            .filter_map(|gamepad| gamepad.port)
+            .collect();
+
+        match self.config.gamepad_ports.get(&info.uuid) {
+            Some(Some(port)) if !taken.contains(port) => Some(*port),
+            Some(None) => None,
+            _ => Port::ALL.into_iter().find(|port| !taken.contains(port)),
+        }
+    }
+
+    pub fn update_title(&mut self) -> Task<cosmic::Action<Message>> {
+        let mut window_title = fl!("app-title");
+
+        if let Some(emulator) = &self.emulator {
+            // Prefer the proper title from the ROM database over the file name.
+            let rom_name = match &emulator.rom_info().game {
+                Some(game) => Some(game.title.clone()),
+                None => emulator
+                    .rom_path()
+                    .file_name()
+                    .map(|f| f.to_string_lossy().into_owned()),
+            };
+            if let Some(rom_name) = rom_name {
+                window_title.push_str(" — ");
+                window_title.push_str(&rom_name);
+            }
+        }
+
+        if let Some(id) = self.core.main_window_id() {
+            self.set_window_title(window_title, id)
+        } else {
+            Task::none()
+        }
+    }
+
+    fn open_rom(&mut self, rom_path: PathBuf) -> Task<cosmic::Action<Message>> {
+        self.open_patched_rom(rom_path, None)
+    }
+
+    /// Opens a ROM, asking which one to play if it's an archive holding several. Without an
+    /// explicit patch, a patch next to the ROM is applied if there is one.
+    fn open_patched_rom(
+        &mut self,
+        rom_path: PathBuf,
+        patch_path: Option<PathBuf>,
+    ) -> Task<cosmic::Action<Message>> {
+        if let Some(kind) = ArchiveKind::of(&rom_path) {
+            match archive::rom_entries(&rom_path, kind) {
+                Ok(entries) if entries.len() > 1 => {
+                    self.archive_prompt = Some(ArchivePrompt {
+                        path: rom_path,
+                        entries,
+                        patch: patch_path,
+                    });
+                    return Task::none();
+                }
+                // Without a choice to make the ROM is found again on its own, which keeps its
+                // battery save named after the archive alone.
+                Ok(_) => return self.load_game(rom_path, None, patch_path),
+                Err(err) => {
+                    tracing::error!("error reading archive {}: {}", rom_path.display(), err);
+                    return self.show_toast(fl!(
+                        "rom-load-error",
+                        file = file_name_lossy(&rom_path),
+                        reason = err.to_string()
+                    ));
+                }
+            }
+        }
+
+        self.load_game(rom_path, None, patch_path)
+    }
+
+    /// Loads a ROM, replacing the running game. If it fails to load the error is shown to the
+    /// user and the current game keeps running.
+    fn load_game(
+        &mut self,
+        rom_path: PathBuf,
+        entry: Option<String>,
+        patch_path: Option<PathBuf>,
+    ) -> Task<cosmic::Action<Message>> {
+        let rom = match load_rom(&rom_path, entry.as_deref(), patch_path.as_deref()) {
+            Ok(rom) => rom,
+            Err(err) => {
+                tracing::error!("error loading rom {}: {}", rom_path.display(), err);
+                return self.show_toast(fl!(
+                    "rom-load-error",
+                    file = file_name_lossy(&rom_path),
+                    reason = err.to_string()
+                ));
+            }
+        };
+
+        tracing::info!("{:?}", rom);
+        self.record_opened(rom_path.canonicalize().unwrap_or_else(|_| rom_path.clone()));
+        self.rom_entry = entry;
+        let patch_toast = match (&rom.info.patch, &rom.info.skipped_patch) {
+            (Some(patch_path), _) => {
+                self.show_toast(fl!("patch-applied", file = file_name_lossy(patch_path)))
+            }
+            (None, Some(patch_path)) => {
+                self.show_toast(fl!("patch-skipped", file = file_name_lossy(patch_path)))
+            }
+            (None, None) => Task::none(),
+        };
+        if let Some(emulator) = &mut self.emulator {
+            emulator.load_rom(rom, rom_path);
+        } else {
+            self.emulator = Some(self.new_emulator(rom, rom_path));
+        }
+
+        Task::batch([self.update_title(), patch_toast])
+    }
+
+    /// Reloads the running game with a patch applied. The patched ROM only lives in memory. Its
+    /// battery save is named after the ROM file, so it's shared with the unpatched game, but
+    /// save states are named after the patched ROM's checksum and are kept apart.
+    fn apply_patch(&mut self, patch_path: PathBuf) -> Task<cosmic::Action<Message>> {
+        let Some(emulator) = &self.emulator else {
+            return self.show_toast(fl!("patch-needs-game"));
+        };
+
+        let rom_path = emulator.rom_path().to_path_buf();
+        self.load_game(rom_path, self.rom_entry.clone(), Some(patch_path))
+    }
+
+    fn show_toast(&mut self, message: String) -> Task<cosmic::Action<Message>> {
+        self.toasts
+            .push(Toast::new(message))
+            .map(cosmic::Action::App)
+    }
+
+    fn new_emulator(&self, rom: Rom, rom_path: PathBuf) -> Emulator {
+        let mut emulator = Emulator::new(
+            rom,
+            rom_path,
+            keymap::keymap(&self.config.key_bindings()),
+            self.open_audio(),
+        );
+        AppModel::apply_config(&self.config, &mut emulator);
+        emulator.set_palette(load_palette(&self.config));
+        emulator
+    }
+
+    fn recording(&self) -> Option<RecordingKind> {
+        self.emulator.as_ref().and_then(Emulator::recording)
+    }
+
+    fn audio_backend(&self) -> AudioBackend {
+        self.audio_backend_override
+            .unwrap_or(self.config.audio_backend)
+    }
+
+    /// Opens the audio backend chosen on the command line or in the settings.
+    fn open_audio(&self) -> AudioOutput {
+        let wav_path = self
+            .audio_file_override
+            .clone()
+            .or_else(|| self.config.audio_wav_path.clone())
+            .unwrap_or_else(audio::default_wav_path);
+        AudioOutput::open(self.audio_backend(), &wav_path, APU_SAMPLE_RATE)
+    }
+
+    fn reopen_audio(&mut self) {
+        if let Some(mut emulator) = self.emulator.take() {
+            // Drop the old output first so that a WAV file is finished before it's reopened.
+            emulator.set_audio_output(AudioOutput::Null);
+            emulator.set_audio_output(self.open_audio());
+            self.emulator = Some(emulator);
+        }
+    }
+
+    fn apply_config(config: &Config, emulator: &mut Emulator) {
+        emulator.configure_rewind(
+            config.rewind_interval_frames,
+            config.rewind_buffer_mb as usize * 1024 * 1024,
+        );
+        emulator.set_keymap(keymap::keymap(&config.key_bindings()));
+        emulator.set_ntsc_filter(config.ntsc_filter);
+    }
+
+    fn key_binds() -> HashMap<KeyBind, MenuAction> {
+        let mut key_binds = HashMap::new();
+        key_binds.insert(
+            KeyBind {
+                modifiers: vec![],
+                key: Key::Named(Named::F5),
+            },
+            MenuAction::SaveState,
+        );
+        key_binds.insert(
+            KeyBind {
+                modifiers: vec![],
+                key: Key::Named(Named::F7),
+            },
+            MenuAction::LoadState,
+        );
+        key_binds.insert(
+            KeyBind {
+                modifiers: vec![],
+                key: Key::Named(Named::F6),
+            },
+            MenuAction::FrameAdvance,
+        );
+        key_binds.insert(
+            KeyBind {
+                modifiers: vec![Modifier::Shift],
+                key: Key::Named(Named::F6),
+            },
+            MenuAction::StepInstruction,
+        );
+        key_binds.insert(
+            KeyBind {
+                modifiers: vec![],
+                key: Key::Named(Named::F12),
+            },
+            MenuAction::TakeScreenshot,
+        );
+        key_binds
+    }
+}
+
+fn nes_button_label(button: NesButton) -> String {
+    match button {
+        NesButton::A => fl!("button-a"),
+        NesButton::B => fl!("button-b"),
+        NesButton::Select => fl!("button-select"),
+        NesButton::Start => fl!("button-start"),
+        NesButton::Up => fl!("button-up"),
+        NesButton::Down => fl!("button-down"),
+        NesButton::Left => fl!("button-left"),
+        NesButton::Right => fl!("button-right"),
+    }
+}
+
+/// Shows the frame counter and the buttons held on each controller over the screen while a movie
+/// is running.
+fn movie_overlay<'a>(progress: MovieProgress) -> Element<'a, Message> {
+    let space_xxs = theme::active().cosmic().spacing.space_xxs;
+
+    let counter = match progress.length {
+        Some(length) => fl!("movie-playing", frame = progress.frame, length = length),
+        None => fl!("movie-recording", frame = progress.frame),
+    };
+    let buttons = format!(
+        "1P {}  2P {}",
+        movie::format_buttons(progress.buttons[0]),
+        movie::format_buttons(progress.buttons[1])
+    );
+
+    widget::container(
+        widget::container(
+            widget::column()
+                .push(widget::text::monotext(counter))
+                .push(widget::text::monotext(buttons)),
+        )
+        .padding(space_xxs)
+        .class(theme::Container::Dialog),
+    )
+    .padding(space_xxs)
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .into()
+}
+
+/// The palette chosen in the config. A custom palette that can't be loaded falls back to the
+/// default one.
+fn load_palette(config: &Config) -> Palette {
+    if config.palette != PaletteChoice::Custom {
+        return Palette::built_in(config.palette);
+    }
+
+    let Some(path) = &config.custom_palette else {
+        return Palette::default();
+    };
+    Palette::load(path).unwrap_or_else(|err| {
+        tracing::error!("error loading palette {}: {}", path.display(), err);
+        Palette::default()
+    })
+}
+
+fn palette_label(choice: PaletteChoice) -> String {
+    match choice {
+        PaletteChoice::Default => fl!("palette-default"),
+        PaletteChoice::Fceux => fl!("palette-fceux"),
+        PaletteChoice::Nestopia => fl!("palette-nestopia"),
+        PaletteChoice::Smooth => fl!("palette-smooth"),
+        PaletteChoice::Custom => fl!("palette-custom"),
+    }
+}
+
+fn ntsc_preset_label(preset: NtscPreset) -> String {
+    match preset {
+        NtscPreset::Composite => fl!("ntsc-composite"),
+        NtscPreset::SVideo => fl!("ntsc-svideo"),
+        NtscPreset::Rgb => fl!("ntsc-rgb"),
+        NtscPreset::Monochrome => fl!("ntsc-monochrome"),
+    }
+}
+
+fn audio_backend_label(backend: AudioBackend) -> String {
+    match backend {
+        AudioBackend::Cpal => fl!("audio-system-default"),
+        AudioBackend::Null => fl!("audio-none"),
+        AudioBackend::Wav => fl!("audio-wav-file"),
+    }
+}
+
+fn file_name_lossy(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn info_item<'a>(label: impl Into<String>, value: impl Into<String>) -> Element<'a, Message> {
+    widget::settings::item(label.into(), widget::text::body(value.into())).into()
+}
 
-    fn keymap() -> HashMap<KeyCode, Button> {
-        let mut keymap = HashMap::new();
-        keymap.insert(KeyCode::KeyX, Button::A);
-        keymap.insert(KeyCode::KeyZ, Button::B);
-        keymap.insert(KeyCode::Space, Button::Select);
-        keymap.insert(KeyCode::Enter, Button::Start);
-        keymap.insert(KeyCode::ArrowUp, Button::Up);
-        keymap.insert(KeyCode::ArrowDown, Button::Down);
-        keymap.insert(KeyCode::ArrowLeft, Button::Left);
-        keymap.insert(KeyCode::ArrowRight, Button::Right);
-        keymap
+fn speed_label(speed: EmulationSpeed) -> String {
+    match speed.multiplier() {
+        Some(multiplier) => fl!("speed-multiplier", multiplier = multiplier.to_string()),
+        None => fl!("speed-unthrottled"),
     }
 }
 
@@ -409,14 +2372,35 @@ impl AppModel {
 pub enum ContextPage {
     #[default]
     About,
+    Settings,
+    RomInfo,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum MenuAction {
     About,
+    Settings,
+    RomInfo,
     OpenFile,
+    OpenPatch,
     ToggleEmulation,
     ResetEmulation,
+    SaveState,
+    LoadState,
+    SelectSaveSlot(u8),
+    SetSpeed(EmulationSpeed),
+    FrameAdvance,
+    StepInstruction,
+    OpenRecentRom(usize),
+    ClearRecentRoms,
+    TakeScreenshot,
+    ToggleRecording(RecordingKind),
+    RecordMovie(MovieStart),
+    PlayMovie,
+    StopMovie,
+    SetPalette(PaletteChoice),
+    ChoosePaletteFile,
+    SetNtscFilter(Option<NtscPreset>),
 }
 
 impl menu::action::MenuAction for MenuAction {
@@ -425,9 +2409,28 @@ impl menu::action::MenuAction for MenuAction {
     fn message(&self) -> Self::Message {
         match self {
             MenuAction::About => Message::ToggleContextPage(ContextPage::About),
+            MenuAction::Settings => Message::ToggleContextPage(ContextPage::Settings),
+            MenuAction::RomInfo => Message::ToggleContextPage(ContextPage::RomInfo),
             MenuAction::OpenFile => Message::OpenFileDialog,
+            MenuAction::OpenPatch => Message::OpenPatchDialog,
             MenuAction::ToggleEmulation => Message::ToggleEmulation,
             MenuAction::ResetEmulation => Message::ResetEmulation,
+            MenuAction::SaveState => Message::SaveState,
+            MenuAction::LoadState => Message::LoadState,
+            MenuAction::SelectSaveSlot(slot) => Message::SelectSaveSlot(*slot),
+            MenuAction::SetSpeed(speed) => Message::SetSpeed(*speed),
+            MenuAction::FrameAdvance => Message::FrameAdvance,
+            MenuAction::StepInstruction => Message::StepInstruction,
+            MenuAction::OpenRecentRom(index) => Message::OpenRecentRom(*index),
+            MenuAction::ClearRecentRoms => Message::ClearRecentRoms,
+            MenuAction::TakeScreenshot => Message::TakeScreenshot,
+            MenuAction::ToggleRecording(kind) => Message::ToggleRecording(*kind),
+            MenuAction::RecordMovie(start) => Message::RecordMovie(*start),
+            MenuAction::PlayMovie => Message::PlayMovie,
+            MenuAction::StopMovie => Message::StopMovie,
+            MenuAction::SetPalette(choice) => Message::SetPalette(*choice),
+            MenuAction::ChoosePaletteFile => Message::ChoosePaletteFile,
+            MenuAction::SetNtscFilter(preset) => Message::SetNtscFilter(*preset),
         }
     }
 }
@@ -0,0 +1,130 @@
+use crate::emulator::RomLoadError;
+use crate::rom::INES_MAGIC;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Compressed file formats ROMs can be read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    #[cfg(feature = "7z")]
+    SevenZip,
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+impl ArchiveKind {
+    pub fn of(path: &Path) -> Option<ArchiveKind> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "zip" => Some(ArchiveKind::Zip),
+            #[cfg(feature = "7z")]
+            "7z" => Some(ArchiveKind::SevenZip),
+            #[cfg(feature = "gzip")]
+            "gz" => Some(ArchiveKind::Gzip),
+            _ => None,
+        }
+    }
+}
+
+/// File extensions of the archive formats that are supported in this build.
+pub fn extensions() -> Vec<&'static str> {
+    let mut extensions = vec!["zip"];
+    if cfg!(feature = "7z") {
+        extensions.push("7z");
+    }
+    if cfg!(feature = "gzip") {
+        extensions.push("gz");
+    }
+    extensions
+}
+
+/// Lists the entries of an archive that start with an iNES header.
+pub fn rom_entries(path: &Path, kind: ArchiveKind) -> Result<Vec<String>, RomLoadError> {
+    let mut names = Vec::new();
+    for_each_entry(path, kind, |name, reader| {
+        let mut magic = [0u8; INES_MAGIC.len()];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == INES_MAGIC => names.push(name.to_string()),
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(err) => return Err(err),
+        }
+        Ok(true)
+    })?;
+    Ok(names)
+}
+
+/// Reads the entry called `entry` out of an archive, or if no entry is given, the first one
+/// that starts with an iNES header. Readmes and other files that are often bundled with ROMs are
+/// skipped.
+pub fn read_rom(
+    path: &Path,
+    kind: ArchiveKind,
+    entry: Option<&str>,
+) -> Result<Vec<u8>, RomLoadError> {
+    let mut rom = None;
+    for_each_entry(path, kind, |name, reader| {
+        if entry.is_some_and(|entry| entry != name) {
+            return Ok(true);
+        }
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        if entry.is_some() || data.starts_with(INES_MAGIC) {
+            rom = Some(data);
+            return Ok(false);
+        }
+        Ok(true)
+    })?;
+
+    rom.ok_or(RomLoadError::NoRomInArchive)
+}
+
+/// Calls `f` with the name and contents of each file in the archive until it returns `false`.
+fn for_each_entry<F>(path: &Path, kind: ArchiveKind, mut f: F) -> Result<(), RomLoadError>
+where
+    F: FnMut(&str, &mut dyn Read) -> io::Result<bool>,
+{
+    let file = File::open(path)?;
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for index in 0..zip.len() {
+                let mut zip_file = zip.by_index(index)?;
+                if zip_file.is_dir() {
+                    continue;
+                }
+                let name = zip_file.name().to_string();
+                if !f(&name, &mut zip_file)? {
+                    break;
+                }
+            }
+        }
+        #[cfg(feature = "7z")]
+        ArchiveKind::SevenZip => {
+            let len = file.metadata()?.len();
+            let mut archive =
+                sevenz_rust::SevenZReader::new(file, len, sevenz_rust::Password::empty())?;
+            archive.for_each_entries(|entry, reader| {
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+                Ok(f(entry.name(), reader)?)
+            })?;
+        }
+        #[cfg(feature = "gzip")]
+        ArchiveKind::Gzip => {
+            // A gzip file holds a single file, named after the archive without the `.gz`.
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            f(&name, &mut flate2::read::GzDecoder::new(file))?;
+        }
+    }
+
+    Ok(())
+}
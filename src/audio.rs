@@ -1,15 +1,184 @@
+use crate::capture;
 use rustednes_common::audio::{AudioDriver, LinearResampler, SampleBuffer};
 use rustednes_common::time::TimeSource;
 
 use rustednes_core::sink::AudioSink;
 
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{self, AtomicU64};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{SampleFormat, Stream};
+use serde::{Deserialize, Serialize};
 use tracing::error;
 
+/// Where the emulator's sound goes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize, clap::ValueEnum)]
+pub enum AudioBackend {
+    /// The system's default output device.
+    #[default]
+    Cpal,
+    /// No sound at all.
+    Null,
+    /// A WAV file.
+    Wav,
+}
+
+impl AudioBackend {
+    pub const ALL: [AudioBackend; 3] = [AudioBackend::Cpal, AudioBackend::Null, AudioBackend::Wav];
+}
+
+/// The WAV file written to when the config doesn't name one. Each call gives a new file named
+/// after the current time, so earlier recordings are never overwritten.
+pub fn default_wav_path() -> PathBuf {
+    let dir = dirs::audio_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default();
+    let name = capture::timestamped_name(&dir, "rustednes-cosmic", &["wav"]);
+    dir.join(format!("{name}.wav"))
+}
+
+/// An audio backend along with the clock that drives emulation timing.
+pub enum AudioOutput {
+    Cpal(CpalDriver),
+    Null,
+    Wav(WavDriver),
+}
+
+impl AudioOutput {
+    /// Opens the requested backend, falling back to no sound if it can't be opened so that a
+    /// missing sound card doesn't stop games from running.
+    pub fn open(backend: AudioBackend, wav_path: &Path, input_sample_rate: u32) -> AudioOutput {
+        let output = match backend {
+            AudioBackend::Cpal => CpalDriver::new(input_sample_rate).map(|driver| {
+                tracing::info!("Audio sample rate: {}", driver.sample_rate());
+                AudioOutput::Cpal(driver)
+            }),
+            AudioBackend::Null => Ok(AudioOutput::Null),
+            AudioBackend::Wav => WavDriver::new(wav_path, input_sample_rate).map(AudioOutput::Wav),
+        };
+
+        output.unwrap_or_else(|err| {
+            error!(
+                "failed to open {:?} audio output, sound is disabled: {}",
+                backend, err
+            );
+            AudioOutput::Null
+        })
+    }
+
+    pub fn sink(&self) -> AudioOutputSink {
+        match self {
+            AudioOutput::Cpal(driver) => AudioOutputSink::Cpal(driver.sink()),
+            AudioOutput::Null => AudioOutputSink::Null(NullAudioSink),
+            AudioOutput::Wav(driver) => AudioOutputSink::Wav(driver.sink()),
+        }
+    }
+
+    /// The clock emulation is synced to. Only a real output device consumes samples at a steady
+    /// rate, so the other backends follow the wall clock.
+    pub fn time_source(&self) -> Box<dyn TimeSource> {
+        match self {
+            AudioOutput::Cpal(driver) => Box::new(driver.time_source()),
+            AudioOutput::Null | AudioOutput::Wav(_) => Box::<WallClockTimeSource>::default(),
+        }
+    }
+}
+
+pub enum AudioOutputSink {
+    Cpal(CpalDriverBufferSink),
+    Null(NullAudioSink),
+    Wav(WavSink),
+}
+
+impl AudioSink for AudioOutputSink {
+    fn write_sample(&mut self, sample: f32) {
+        match self {
+            AudioOutputSink::Cpal(sink) => sink.write_sample(sample),
+            AudioOutputSink::Null(sink) => sink.write_sample(sample),
+            AudioOutputSink::Wav(sink) => sink.write_sample(sample),
+        }
+    }
+
+    fn samples_written(&self) -> usize {
+        match self {
+            AudioOutputSink::Cpal(sink) => sink.samples_written(),
+            AudioOutputSink::Null(sink) => sink.samples_written(),
+            AudioOutputSink::Wav(sink) => sink.samples_written(),
+        }
+    }
+}
+
+pub struct WallClockTimeSource {
+    start: Instant,
+}
+
+impl Default for WallClockTimeSource {
+    fn default() -> Self {
+        WallClockTimeSource {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for WallClockTimeSource {
+    fn time_ns(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+}
+
+type WavWriter = hound::WavWriter<BufWriter<File>>;
+
+/// Writes the emulator's sound to a 16-bit mono WAV file at the APU's sample rate. The file is
+/// finished off when the driver and all of its sinks have been dropped.
+pub struct WavDriver {
+    writer: Arc<Mutex<WavWriter>>,
+}
+
+impl WavDriver {
+    pub fn new(path: &Path, sample_rate: u32) -> Result<WavDriver, Box<dyn std::error::Error>> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path, spec)?;
+        tracing::info!("Writing audio to {}", path.display());
+
+        Ok(WavDriver {
+            writer: Arc::new(Mutex::new(writer)),
+        })
+    }
+
+    pub fn sink(&self) -> WavSink {
+        WavSink {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+pub struct WavSink {
+    writer: Arc<Mutex<WavWriter>>,
+}
+
+impl AudioSink for WavSink {
+    fn write_sample(&mut self, sample: f32) {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        if let Err(err) = self.writer.lock().unwrap().write_sample(sample) {
+            error!("failed to write audio sample: {}", err);
+        }
+    }
+
+    fn samples_written(&self) -> usize {
+        self.writer.lock().unwrap().len() as usize
+    }
+}
+
 pub struct CpalDriverBufferSink {
     sample_buffer: Arc<Mutex<SampleBuffer>>,
 }
@@ -26,6 +195,54 @@ impl AudioSink for CpalDriverBufferSink {
     }
 }
 
+/// Discards every sample, used while the emulator output should be muted.
+pub struct NullAudioSink;
+
+impl AudioSink for NullAudioSink {
+    fn write_sample(&mut self, _sample: f32) {}
+
+    fn samples_written(&self) -> usize {
+        0
+    }
+}
+
+/// Wraps another sink, dropping or repeating samples so that audio generated at a different
+/// emulation speed still arrives at the output at real-time rate.
+pub struct SpeedAdjustedSink<S> {
+    inner: S,
+    step: f64,
+    position: f64,
+}
+
+impl<S> SpeedAdjustedSink<S> {
+    /// A `multiplier` of zero discards all samples.
+    pub fn new(inner: S, multiplier: f64) -> Self {
+        SpeedAdjustedSink {
+            inner,
+            step: if multiplier > 0.0 {
+                1.0 / multiplier
+            } else {
+                0.0
+            },
+            position: 0.0,
+        }
+    }
+}
+
+impl<S: AudioSink> AudioSink for SpeedAdjustedSink<S> {
+    fn write_sample(&mut self, sample: f32) {
+        self.position += self.step;
+        while self.position >= 1.0 {
+            self.inner.write_sample(sample);
+            self.position -= 1.0;
+        }
+    }
+
+    fn samples_written(&self) -> usize {
+        self.inner.samples_written()
+    }
+}
+
 pub struct CpalDriverTimeSource {
     samples_written: Arc<AtomicU64>,
     sample_rate: u32,
@@ -51,9 +268,9 @@ impl CpalDriver {
         let device = host
             .default_output_device()
             .ok_or("failed to get default output device")?;
-        let default_input_config = device.default_input_config()?;
+        let default_output_config = device.default_output_config()?;
 
-        let config = default_input_config.config();
+        let config = default_output_config.config();
         let channels = config.channels as usize;
         let output_sample_rate = config.sample_rate.0;
 
@@ -65,7 +282,7 @@ impl CpalDriver {
         let output_samples_written = samples_written.clone();
 
         let err_fn = |err| error!("an error occurred on the output audio stream: {}", err);
-        let stream = match default_input_config.sample_format() {
+        let stream = match default_output_config.sample_format() {
             SampleFormat::F32 => device.build_output_stream(
                 &config,
                 move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
@@ -112,7 +329,7 @@ impl CpalDriver {
                 err_fn,
                 None,
             ),
-            format => panic!("unsupported audio sample format: {:?}", format),
+            format => return Err(format!("unsupported audio sample format: {:?}", format).into()),
         };
 
         let stream = stream?;
@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Names the files that screenshots, recordings and audio dumps are written to.
+
+use chrono::Local;
+use std::path::Path;
+
+/// A file name without extension for a capture of the running game, made from the ROM's name and
+/// the current time, that doesn't clash with an existing file with any of the `extensions`.
+pub fn capture_name(dir: &Path, rom_path: &Path, extensions: &[&str]) -> String {
+    let rom_name = rom_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "capture".to_string());
+    timestamped_name(dir, &rom_name, extensions)
+}
+
+/// A file name without extension made from `prefix` and the current time, that doesn't clash
+/// with an existing file with any of the `extensions`.
+pub fn timestamped_name(dir: &Path, prefix: &str, extensions: &[&str]) -> String {
+    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
+
+    // Captures taken within the same second get a counter so they don't overwrite each other.
+    let mut name = format!("{prefix}_{timestamp}");
+    let mut counter = 1;
+    while extensions
+        .iter()
+        .any(|ext| dir.join(format!("{name}.{ext}")).exists())
+    {
+        counter += 1;
+        name = format!("{prefix}_{timestamp}_{counter}");
+    }
+    name
+}
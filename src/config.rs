@@ -1,11 +1,85 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use cosmic::cosmic_config::{self, cosmic_config_derive::CosmicConfigEntry, CosmicConfigEntry};
 
-#[derive(Debug, Default, Clone, CosmicConfigEntry, Eq, PartialEq)]
+use crate::audio::AudioBackend;
+use crate::gamepad::GamepadMapping;
+use crate::keymap::{self, KeyBindings, Port};
+use crate::ntsc::NtscPreset;
+use crate::palette::PaletteChoice;
+
+#[derive(Debug, Clone, CosmicConfigEntry, Eq, PartialEq)]
 #[version = 1]
 pub struct Config {
-    rom_dir: Option<PathBuf>,
+    /// Directory scanned for the ROM library.
+    pub rom_dir: Option<PathBuf>,
+    /// When each ROM was last opened, in seconds since the Unix epoch.
+    pub last_played: BTreeMap<PathBuf, u64>,
+    /// Recently opened ROMs, most recent first.
+    pub recent_roms: Vec<PathBuf>,
+    /// Number of frames between rewind snapshots.
+    pub rewind_interval_frames: u32,
+    /// Memory used for rewind snapshots, in megabytes. Zero disables rewinding.
+    pub rewind_buffer_mb: u32,
+    pub keyboard_bindings: KeyBindings,
+    pub keyboard_bindings_2: KeyBindings,
+    /// Button mappings for each model of game controller, keyed by its UUID.
+    pub gamepad_mappings: BTreeMap<String, GamepadMapping>,
+    /// The port each model of game controller was assigned to. Controllers without an entry are
+    /// given the first free port.
+    pub gamepad_ports: BTreeMap<String, Option<Port>>,
+    /// Where the emulator's sound goes.
+    pub audio_backend: AudioBackend,
+    /// The file written to by the WAV audio backend, which is overwritten each time. Defaults to a
+    /// new file named after the current time in the music directory.
+    pub audio_wav_path: Option<PathBuf>,
+    /// Directory screenshots are saved to. Defaults to one in the pictures directory.
+    pub screenshot_dir: Option<PathBuf>,
+    /// How many times the NES resolution screenshots are saved at.
+    pub screenshot_scale: u32,
+    /// Directory gameplay recordings are saved to. Defaults to one in the videos directory.
+    pub recording_dir: Option<PathBuf>,
+    /// The colours the screen is drawn with.
+    pub palette: PaletteChoice,
+    /// The `.pal` file used by the custom palette.
+    pub custom_palette: Option<PathBuf>,
+    /// The connection the NTSC filter emulates, or `None` to leave the filter off.
+    pub ntsc_filter: Option<NtscPreset>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rom_dir: None,
+            last_played: BTreeMap::new(),
+            recent_roms: Vec::new(),
+            rewind_interval_frames: 2,
+            rewind_buffer_mb: 64,
+            keyboard_bindings: keymap::default_key_bindings(Port::One),
+            keyboard_bindings_2: keymap::default_key_bindings(Port::Two),
+            gamepad_mappings: BTreeMap::new(),
+            gamepad_ports: BTreeMap::new(),
+            audio_backend: AudioBackend::default(),
+            audio_wav_path: None,
+            screenshot_dir: None,
+            screenshot_scale: 1,
+            recording_dir: None,
+            palette: PaletteChoice::default(),
+            custom_palette: None,
+            ntsc_filter: None,
+        }
+    }
+}
+
+impl Config {
+    /// The keyboard bindings for both controller ports.
+    pub fn key_bindings(&self) -> [KeyBindings; 2] {
+        [
+            self.keyboard_bindings.clone(),
+            self.keyboard_bindings_2.clone(),
+        ]
+    }
 }
@@ -1,61 +1,213 @@
 use crate::{
-    audio::{CpalDriver, CpalDriverTimeSource},
+    archive::{self, ArchiveKind},
+    audio::{AudioOutput, NullAudioSink, SpeedAdjustedSink},
+    gamepad::{GamepadAxis, GamepadId},
+    keymap::{NesButton, Port},
+    movie::{self, Movie, MovieFrame, MovieStart},
+    ntsc::{NtscFilter, NtscPreset},
+    palette::Palette,
+    patch::{self, PatchError},
+    recording::{Recorder, RecordingKind, RecordingSink},
+    rewind::RewindBuffer,
+    rom::{self, Checksums, InvalidHeader, Rom, RomHeader, RomInfo},
+    romdb, sram,
     video::VideoFrameSink,
 };
 use cosmic::iced::keyboard::key::Code as KeyCode;
-use rustednes_common::{audio::AudioDriver, time::TimeSource};
+use rustednes_common::{state::StateManager, time::TimeSource};
 use rustednes_core::{
-    apu::SAMPLE_RATE as APU_SAMPLE_RATE,
-    cartridge::Cartridge,
+    cartridge::{Cartridge, LoadError},
     cpu::CPU_FREQUENCY,
     input::Button,
     nes::Nes,
     ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    sink::AudioSink,
 };
 use std::error::Error;
 use std::{
     collections::HashMap,
-    fs::File,
+    fmt, fs,
+    io::{self, Cursor},
+    mem,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 pub const CPU_CYCLE_TIME_NS: u64 = (1e9_f64 / CPU_FREQUENCY as f64) as u64 + 1;
 
+pub const SAVE_STATE_SLOTS: u8 = 10;
+
+/// Frame rate of the NTSC NES.
+pub const FRAMES_PER_SECOND: f64 = 60.0988;
+
+/// How often battery-backed RAM is written to disk while a game is running.
+const SRAM_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a single tick may run for when the emulation speed is unthrottled.
+const UNTHROTTLED_TICK_BUDGET: Duration = Duration::from_millis(12);
+
+/// Speed used while the fast-forward key is held.
+pub const FAST_FORWARD_SPEED: EmulationSpeed = EmulationSpeed::Quadruple;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EmulationSpeed {
+    Quarter,
+    Half,
+    #[default]
+    Normal,
+    Double,
+    Quadruple,
+    Octuple,
+    Unthrottled,
+}
+
+impl EmulationSpeed {
+    pub const ALL: [EmulationSpeed; 7] = [
+        EmulationSpeed::Quarter,
+        EmulationSpeed::Half,
+        EmulationSpeed::Normal,
+        EmulationSpeed::Double,
+        EmulationSpeed::Quadruple,
+        EmulationSpeed::Octuple,
+        EmulationSpeed::Unthrottled,
+    ];
+
+    /// Returns the speed relative to real time, or `None` if emulation shouldn't be throttled.
+    pub fn multiplier(self) -> Option<f64> {
+        match self {
+            EmulationSpeed::Quarter => Some(0.25),
+            EmulationSpeed::Half => Some(0.5),
+            EmulationSpeed::Normal => Some(1.0),
+            EmulationSpeed::Double => Some(2.0),
+            EmulationSpeed::Quadruple => Some(4.0),
+            EmulationSpeed::Octuple => Some(8.0),
+            EmulationSpeed::Unthrottled => None,
+        }
+    }
+}
+
+/// Something that holds NES buttons down. Each source's buttons are kept apart, so that one
+/// letting go of a button doesn't release it while another still holds it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum InputSource {
+    Keyboard,
+    GamepadButtons(GamepadId),
+    GamepadAxis(GamepadId, GamepadAxis),
+}
+
+/// A movie being recorded or played back.
+enum MovieState {
+    Recording { movie: Movie, path: PathBuf },
+    Playing { movie: Movie, next_frame: usize },
+}
+
+/// Where a running movie is up to, for showing on screen.
+pub struct MovieProgress {
+    pub frame: usize,
+    /// The number of frames in a movie being played back, or `None` while recording.
+    pub length: Option<usize>,
+    /// The buttons held on each controller in the current frame.
+    pub buttons: [u8; 2],
+}
+
 pub struct Emulator {
     nes: Nes,
-    audio_driver: CpalDriver,
-    time_source: CpalDriverTimeSource,
+    audio: AudioOutput,
+    time_source: Box<dyn TimeSource>,
     start_time_ns: u64,
     paused_time_ns: Option<u64>,
     emulated_cycles: u64,
+    /// Frames run since the emulation clock was last restarted.
+    emulated_frames: u64,
     emulated_instructions: u64,
-    // TODO: Handle save states.
-    // state_manager: StateManager,
-    keymap: HashMap<KeyCode, Button>,
+    state_manager: StateManager,
+    rewind_buffer: RewindBuffer,
+    rewind_interval_frames: u32,
+    rewinding: bool,
+    frame_count: u64,
+    speed: EmulationSpeed,
+    fast_forwarding: bool,
+    keymap: HashMap<KeyCode, (Port, Button)>,
     pixels: Vec<u8>,
+    palette: Palette,
+    ntsc: Option<NtscFilter>,
     rom_path: PathBuf,
+    rom_info: RomInfo,
+    saved_sram: Vec<u8>,
+    last_sram_flush: Instant,
+    recorder: Option<Recorder>,
+    movie: Option<MovieState>,
+    /// Set when a movie played to the end, until the app has been told about it.
+    movie_finished: bool,
+    /// The buttons the player is holding on each controller. While a movie is running, they
+    /// only reach the console at the start of a frame so that the movie can be played back
+    /// exactly.
+    held_buttons: [u8; 2],
+    /// The buttons each input source is holding, which make up `held_buttons`.
+    source_buttons: HashMap<InputSource, [u8; 2]>,
+    /// The buttons given to the console at the start of the current frame of a movie.
+    movie_buttons: [u8; 2],
+    /// A reset to record in the next frame of the movie.
+    pending_commands: u8,
+    /// The console's state before the game's battery RAM was loaded, which movies start from.
+    power_on_state: Vec<u8>,
+    /// The console's state from before a movie replaced it, which is put back when the movie
+    /// stops. While it's set battery RAM isn't saved, so the movie's doesn't overwrite the
+    /// player's save.
+    pre_movie_state: Option<Vec<u8>>,
 }
 
 impl Emulator {
-    pub fn new(rom: Cartridge, rom_path: PathBuf, keymap: HashMap<KeyCode, Button>) -> Self {
-        let audio_driver = CpalDriver::new(APU_SAMPLE_RATE).unwrap();
-        let time_source = audio_driver.time_source();
-        tracing::info!("Audio sample rate: {}", audio_driver.sample_rate());
+    pub fn new(
+        rom: Rom,
+        rom_path: PathBuf,
+        keymap: HashMap<KeyCode, (Port, Button)>,
+        audio: AudioOutput,
+    ) -> Self {
+        let time_source = audio.time_source();
         let start_time_ns = time_source.time_ns();
 
-        Self {
-            nes: Nes::new(rom),
-            audio_driver,
+        let mut emulator = Self {
+            nes: Nes::new(rom.cartridge),
+            audio,
             time_source,
             start_time_ns,
             paused_time_ns: None,
             emulated_cycles: 0,
+            emulated_frames: 0,
             emulated_instructions: 0,
-            // state_manager: StateManager::new(rom_path, 10),
+            state_manager: StateManager::new(
+                state_path(&rom_path, &rom.info.checksums),
+                SAVE_STATE_SLOTS,
+            ),
+            rewind_buffer: RewindBuffer::new(0),
+            rewind_interval_frames: 1,
+            rewinding: false,
+            frame_count: 0,
+            speed: EmulationSpeed::default(),
+            fast_forwarding: false,
             keymap,
             pixels: vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            palette: Palette::default(),
+            ntsc: None,
             rom_path,
-        }
+            rom_info: rom.info,
+            saved_sram: Vec::new(),
+            last_sram_flush: Instant::now(),
+            recorder: None,
+            movie: None,
+            movie_finished: false,
+            held_buttons: [0; 2],
+            source_buttons: HashMap::new(),
+            movie_buttons: [0; 2],
+            pending_commands: 0,
+            power_on_state: Vec::new(),
+            pre_movie_state: None,
+        };
+        emulator.save_power_on_state();
+        emulator.restore_sram();
+
+        emulator
     }
 
     pub fn tick(&mut self) {
@@ -63,18 +215,214 @@ impl Emulator {
             return;
         }
 
-        let mut video_sink = VideoFrameSink::new(self.pixels.as_mut_slice());
+        if self.rewinding {
+            self.rewind_step();
+            return;
+        }
+
+        // Movies are played back a frame at a time, timed by the frame rate rather than by the
+        // audio clock.
+        let frame_driven = self.is_playing_movie();
+        let multiplier = self.effective_speed().multiplier();
+        let target = multiplier.map(|multiplier| {
+            let target_time_ns = (self.time_source.time_ns() - self.start_time_ns) as f64;
+            if frame_driven {
+                (target_time_ns * multiplier * FRAMES_PER_SECOND / 1e9) as u64
+            } else {
+                (target_time_ns * multiplier) as u64 / CPU_CYCLE_TIME_NS
+            }
+        });
+        let deadline = Instant::now() + UNTHROTTLED_TICK_BUDGET;
+
+        // Unthrottled emulation runs far too fast to keep up with, so it's muted.
+        let mut audio_sink = SpeedAdjustedSink::new(self.audio.sink(), multiplier.unwrap_or(0.0));
+
+        loop {
+            let caught_up = match target {
+                Some(target) if frame_driven => self.emulated_frames >= target,
+                Some(target) => self.emulated_cycles >= target,
+                None => Instant::now() >= deadline,
+            };
+            // The clock is restarted when a movie ends, so the target no longer applies.
+            if caught_up || (frame_driven && !self.is_playing_movie()) {
+                break;
+            }
+
+            if frame_driven {
+                while !self.step(&mut audio_sink) {}
+            } else {
+                self.step(&mut audio_sink);
+            }
+        }
+
+        if self.rom_info.header.has_battery && self.last_sram_flush.elapsed() >= SRAM_FLUSH_INTERVAL
+        {
+            self.flush_sram();
+        }
+    }
+
+    /// Runs a single CPU instruction, returning whether it completed a frame.
+    fn step<A: AudioSink>(&mut self, audio_sink: &mut A) -> bool {
+        let mut video_sink = VideoFrameSink::new(
+            &mut self.pixels,
+            &self.palette,
+            self.ntsc.as_mut(),
+            emphasis(&self.nes),
+        );
+        let mut audio_sink = RecordingSink::new(audio_sink, self.recorder.as_mut());
+        let (cycles, _) = self.nes.step(&mut video_sink, &mut audio_sink);
+        let frame_written = video_sink.frame_written();
+
+        self.emulated_cycles += cycles as u64;
+        self.emulated_instructions += 1;
+
+        if frame_written {
+            self.frame_finished();
+        }
+
+        frame_written
+    }
+
+    /// Pauses the emulation and runs it until the next frame is complete.
+    pub fn frame_advance(&mut self) {
+        if self.rewinding {
+            return;
+        }
+        if !self.is_paused() {
+            self.pause_emulation();
+        }
+
+        while !self.step(&mut NullAudioSink) {}
+    }
+
+    /// Pauses the emulation and runs a single CPU instruction.
+    pub fn step_instruction(&mut self) {
+        if self.rewinding {
+            return;
+        }
+        if !self.is_paused() {
+            self.pause_emulation();
+        }
+
+        self.step(&mut NullAudioSink);
+    }
+
+    fn frame_finished(&mut self) {
+        self.frame_count += 1;
+        self.emulated_frames += 1;
+        self.advance_movie();
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write_frame(&self.pixels);
+        }
+
+        if self.frame_count % self.rewind_interval_frames as u64 == 0 {
+            self.push_rewind_snapshot();
+        }
+    }
+
+    fn push_rewind_snapshot(&mut self) {
+        if !self.rewind_enabled() {
+            return;
+        }
+
+        match bincode::serialize(&self.nes) {
+            Ok(state) => self.rewind_buffer.push(state),
+            Err(err) => tracing::error!("failed to take rewind snapshot: {}", err),
+        }
+    }
+
+    /// Restores the previous rewind snapshot and runs a single muted frame from it so there is
+    /// something to display. Rewinding is driven by the UI tick instead of the audio clock.
+    fn rewind_step(&mut self) {
+        let state = match self.rewind_buffer.pop() {
+            Ok(Some(state)) => state,
+            Ok(None) => return,
+            Err(err) => {
+                tracing::error!("failed to read rewind snapshot: {}", err);
+                return;
+            }
+        };
+
+        match bincode::deserialize(&state) {
+            Ok(nes) => self.nes = nes,
+            Err(err) => {
+                tracing::error!("failed to restore rewind snapshot: {}", err);
+                return;
+            }
+        }
+        // Snapshots hold the buttons from when they were taken.
+        self.apply_buttons(self.held_buttons);
+
+        let mut audio_sink = NullAudioSink;
+        loop {
+            let mut video_sink = VideoFrameSink::new(
+                &mut self.pixels,
+                &self.palette,
+                self.ntsc.as_mut(),
+                emphasis(&self.nes),
+            );
+            self.nes.step(&mut video_sink, &mut audio_sink);
+            if video_sink.frame_written() {
+                break;
+            }
+        }
+    }
+
+    pub fn configure_rewind(&mut self, interval_frames: u32, buffer_bytes: usize) {
+        self.rewind_interval_frames = interval_frames.max(1);
+        self.rewind_buffer.set_budget(buffer_bytes);
+        if buffer_bytes == 0 {
+            self.rewind_buffer.clear();
+        }
+    }
+
+    pub fn rewind_enabled(&self) -> bool {
+        self.rewind_buffer.budget() > 0
+    }
+
+    pub fn set_rewinding(&mut self, rewinding: bool) {
+        // Rewinding would desync a movie from its input.
+        if self.rewinding == rewinding
+            || (rewinding && (!self.rewind_enabled() || self.movie.is_some()))
+        {
+            return;
+        }
+
+        self.rewinding = rewinding;
+        if !rewinding {
+            self.apply_buttons(self.held_buttons);
+            self.resync_time();
+        }
+    }
 
-        let target_time_ns = self.time_source.time_ns() - self.start_time_ns;
-        let target_cycles = target_time_ns / CPU_CYCLE_TIME_NS;
+    pub fn is_rewinding(&self) -> bool {
+        self.rewinding
+    }
 
-        let mut audio_sink = self.audio_driver.sink();
+    pub fn speed(&self) -> EmulationSpeed {
+        self.speed
+    }
 
-        while self.emulated_cycles < target_cycles {
-            let (cycles, _) = self.nes.step(&mut video_sink, &mut audio_sink);
+    pub fn set_speed(&mut self, speed: EmulationSpeed) {
+        if self.speed != speed {
+            self.speed = speed;
+            self.resync_time();
+        }
+    }
 
-            self.emulated_cycles += cycles as u64;
-            self.emulated_instructions += 1;
+    pub fn set_fast_forwarding(&mut self, fast_forwarding: bool) {
+        if self.fast_forwarding != fast_forwarding {
+            self.fast_forwarding = fast_forwarding;
+            self.resync_time();
+        }
+    }
+
+    fn effective_speed(&self) -> EmulationSpeed {
+        if self.fast_forwarding {
+            FAST_FORWARD_SPEED
+        } else {
+            self.speed
         }
     }
 
@@ -83,10 +431,10 @@ impl Emulator {
     }
 
     pub fn resume_emulation(&mut self) {
-        if let Some(paused_time_ns) = self.paused_time_ns {
-            let paused_duration_ns = self.time_source.time_ns() - paused_time_ns;
-            self.start_time_ns += paused_duration_ns;
-            self.paused_time_ns = None;
+        // Frame advance and single-stepping run cycles while paused, so rather than shifting the
+        // start time by the paused duration the clock is restarted from the current state.
+        if self.paused_time_ns.take().is_some() {
+            self.resync_time();
         }
     }
 
@@ -103,61 +451,643 @@ impl Emulator {
     }
 
     pub fn reset(&mut self) {
+        // A movie resets between frames, so that the reset is part of its input.
+        match self.movie {
+            Some(MovieState::Recording { .. }) => {
+                self.pending_commands |= movie::COMMAND_RESET;
+                return;
+            }
+            Some(MovieState::Playing { .. }) => return,
+            None => {}
+        }
+
         self.nes.reset();
-        self.start_time_ns = self.time_source.time_ns();
-        self.emulated_cycles = 0;
+        self.resync_time();
         self.emulated_instructions = 0;
+        self.rewind_buffer.clear();
     }
 
-    pub fn load_rom(&mut self, rom: Cartridge, rom_path: PathBuf) {
+    pub fn load_rom(&mut self, rom: Rom, rom_path: PathBuf) {
+        self.flush_sram();
+        if let Some(Err(err)) = self.stop_recording() {
+            tracing::error!("error finishing recording: {}", err);
+        }
+        if let Err(err) = self.stop_movie() {
+            tracing::error!("error saving movie: {}", err);
+        }
         self.reset();
-        self.nes = Nes::new(rom);
+        self.nes = Nes::new(rom.cartridge);
+        self.state_manager =
+            StateManager::new(state_path(&rom_path, &rom.info.checksums), SAVE_STATE_SLOTS);
         self.rom_path = rom_path;
-        // self.state_manager: StateManager::new(rom_path, 10),
+        self.rom_info = rom.info;
+        self.pre_movie_state = None;
+        self.save_power_on_state();
+        self.restore_sram();
+    }
+
+    fn save_power_on_state(&mut self) {
+        self.power_on_state = match bincode::serialize(&self.nes) {
+            Ok(state) => state,
+            Err(err) => {
+                tracing::error!("failed to save power on state: {}", err);
+                Vec::new()
+            }
+        };
+    }
+
+    /// Starts recording a movie, which is saved to `path` when it's stopped.
+    pub fn record_movie(&mut self, path: PathBuf, start: MovieStart) -> Result<(), Box<dyn Error>> {
+        self.stop_movie()?;
+
+        let start_state = match start {
+            MovieStart::PowerOn => {
+                self.detach_from_game()?;
+                self.nes = bincode::deserialize(&self.power_on_state)?;
+                None
+            }
+            MovieStart::CurrentState => Some(bincode::serialize(&self.nes)?),
+        };
+        let rom_filename = self
+            .rom_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let movie = Movie::new(rom_filename, self.rom_info.checksums.md5, start_state);
+
+        self.movie = Some(MovieState::Recording { movie, path });
+        self.start_movie();
+        Ok(())
+    }
+
+    /// Plays a movie back from its start, ignoring the player's input until it ends.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), Box<dyn Error>> {
+        self.stop_movie()?;
+
+        self.detach_from_game()?;
+        let start_state = movie.start_state.as_ref().unwrap_or(&self.power_on_state);
+        self.nes = bincode::deserialize(start_state)?;
+
+        self.movie = Some(MovieState::Playing {
+            movie,
+            next_frame: 0,
+        });
+        self.start_movie();
+        Ok(())
+    }
+
+    /// Saves the player's game before a movie replaces the console's state, so it can be put
+    /// back when the movie stops.
+    fn detach_from_game(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush_sram();
+        if self.pre_movie_state.is_none() {
+            self.pre_movie_state = Some(bincode::serialize(&self.nes)?);
+        }
+        Ok(())
+    }
+
+    /// Puts back the state the console was in before a movie replaced it, which lets battery RAM
+    /// be saved again.
+    fn restore_pre_movie_state(&mut self) {
+        let Some(state) = self.pre_movie_state.take() else {
+            return;
+        };
+
+        match bincode::deserialize(&state) {
+            Ok(nes) => {
+                self.nes = nes;
+                self.rewind_buffer.clear();
+            }
+            Err(err) => {
+                // Stay detached, as saving now would write the movie's battery RAM.
+                tracing::error!("failed to restore the game after the movie: {}", err);
+                self.pre_movie_state = Some(state);
+            }
+        }
+    }
+
+    fn start_movie(&mut self) {
+        self.pending_commands = 0;
+        self.movie_finished = false;
+        self.rewinding = false;
+        self.rewind_buffer.clear();
+        self.resync_time();
+        self.advance_movie();
+    }
+
+    /// Stops recording or playing back a movie, going back to the game that was running before
+    /// it. A recorded movie is saved, and its path is returned.
+    pub fn stop_movie(&mut self) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let movie = self.movie.take();
+        self.restore_pre_movie_state();
+        self.apply_buttons(self.held_buttons);
+        self.resync_time();
+
+        match movie {
+            Some(MovieState::Recording { movie, path }) => {
+                movie.save(&path)?;
+                Ok(Some(path))
+            }
+            Some(MovieState::Playing { .. }) | None => Ok(None),
+        }
+    }
+
+    /// Moves a movie on to the next frame at the start of a frame, recording the player's input
+    /// or giving the console the movie's.
+    fn advance_movie(&mut self) {
+        let frame = match &mut self.movie {
+            None => return,
+            Some(MovieState::Recording { movie, .. }) => {
+                let frame = MovieFrame {
+                    commands: mem::take(&mut self.pending_commands),
+                    buttons: self.held_buttons,
+                };
+                movie.frames.push(frame);
+                frame
+            }
+            Some(MovieState::Playing { movie, next_frame }) => {
+                match movie.frames.get(*next_frame) {
+                    Some(frame) => {
+                        *next_frame += 1;
+                        *frame
+                    }
+                    None => {
+                        self.movie = None;
+                        self.movie_finished = true;
+                        self.restore_pre_movie_state();
+                        self.apply_buttons(self.held_buttons);
+                        self.resync_time();
+                        return;
+                    }
+                }
+            }
+        };
+
+        if frame.commands & movie::COMMAND_POWER != 0 {
+            match bincode::deserialize(&self.power_on_state) {
+                Ok(nes) => self.nes = nes,
+                Err(err) => tracing::error!("failed to restore power on state: {}", err),
+            }
+        } else if frame.commands & movie::COMMAND_RESET != 0 {
+            self.nes.reset();
+        }
+        self.apply_buttons(frame.buttons);
+        self.movie_buttons = frame.buttons;
+    }
+
+    fn is_playing_movie(&self) -> bool {
+        matches!(self.movie, Some(MovieState::Playing { .. }))
+    }
+
+    pub fn movie_progress(&self) -> Option<MovieProgress> {
+        let (frame, length) = match self.movie.as_ref()? {
+            MovieState::Recording { movie, .. } => (movie.frames.len(), None),
+            MovieState::Playing { movie, next_frame } => (*next_frame, Some(movie.frames.len())),
+        };
+        Some(MovieProgress {
+            frame,
+            length,
+            buttons: self.movie_buttons,
+        })
+    }
+
+    /// Returns whether a movie has played to the end since this was last called.
+    pub fn take_movie_finished(&mut self) -> bool {
+        mem::take(&mut self.movie_finished)
+    }
+
+    fn apply_buttons(&mut self, buttons: [u8; 2]) {
+        for port in Port::ALL {
+            for button in NesButton::ALL {
+                let button = button.button();
+                let pressed = buttons[port.index()] & movie::button_bit(button) != 0;
+                set_button_pressed(&mut self.nes, port, button, pressed);
+            }
+        }
+    }
+
+    /// Writes battery-backed RAM to the ROM's `.sav` file if it changed since the last write.
+    pub fn flush_sram(&mut self) {
+        if !self.rom_info.header.has_battery || self.pre_movie_state.is_some() {
+            return;
+        }
+
+        self.last_sram_flush = Instant::now();
+
+        // The cartridge's RAM is read directly rather than over the CPU bus, which gives open bus
+        // values while the mapper has the RAM disabled.
+        let sram = self.nes.prg_ram();
+        if sram == self.saved_sram.as_slice() {
+            return;
+        }
+
+        let sav_path = sram::sav_path(&self.rom_path, self.rom_info.entry.as_deref());
+        match sram::save(&sav_path, sram) {
+            Ok(()) => {
+                tracing::debug!("Saved battery RAM to {}", sav_path.display());
+                self.saved_sram = sram.to_vec();
+            }
+            Err(err) => tracing::error!("failed to write {}: {}", sav_path.display(), err),
+        }
+    }
+
+    fn restore_sram(&mut self) {
+        if !self.rom_info.header.has_battery {
+            return;
+        }
+
+        let sav_path = sram::sav_path(&self.rom_path, self.rom_info.entry.as_deref());
+        match sram::load(&sav_path) {
+            Ok(Some(data)) => {
+                tracing::info!("Loading battery RAM from {}", sav_path.display());
+                // Written straight into the cartridge, as mappers like MMC3 power on with their RAM
+                // disabled and would ignore writes over the CPU bus.
+                let prg_ram = self.nes.prg_ram_mut();
+                let len = data.len().min(prg_ram.len());
+                prg_ram[..len].copy_from_slice(&data[..len]);
+            }
+            Ok(None) => {}
+            Err(err) => tracing::error!("failed to read {}: {}", sav_path.display(), err),
+        }
+
+        self.saved_sram = self.nes.prg_ram().to_vec();
+        self.last_sram_flush = Instant::now();
+    }
+
+    pub fn save_state(&mut self) -> Result<(), Box<dyn Error>> {
+        self.state_manager.save_state(&self.nes)?;
+        Ok(())
+    }
+
+    pub fn load_state(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.movie.is_some() {
+            return Err("can't load a state while a movie is running".into());
+        }
+
+        self.state_manager.load_state(&mut self.nes)?;
+        // The state brings back the buttons that were held when it was saved, not the ones
+        // held now.
+        self.apply_buttons(self.held_buttons);
+        // The loaded state has its own notion of elapsed cycles, so start counting from now
+        // instead of trying to catch up with the time that passed before the load.
+        self.resync_time();
+        self.rewind_buffer.clear();
+        Ok(())
+    }
+
+    pub fn save_slot(&self) -> u8 {
+        self.state_manager.slot()
+    }
+
+    pub fn set_save_slot(&mut self, slot: u8) {
+        self.state_manager.set_slot(slot.min(SAVE_STATE_SLOTS - 1));
+    }
+
+    /// Restart the emulation clock at the current time source position.
+    fn resync_time(&mut self) {
+        let now_ns = self.time_source.time_ns();
+        self.start_time_ns = now_ns;
+        self.emulated_cycles = 0;
+        self.emulated_frames = 0;
+        if self.paused_time_ns.is_some() {
+            self.paused_time_ns = Some(now_ns);
+        }
+    }
+
+    /// Starts recording to `dir`, returning the path of the video, or the audio if only audio
+    /// is recorded. Any recording already running is finished first.
+    pub fn start_recording(
+        &mut self,
+        dir: &Path,
+        kind: RecordingKind,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(Err(err)) = self.stop_recording() {
+            tracing::error!("error finishing recording: {}", err);
+        }
+
+        let recorder = Recorder::start(dir, &self.rom_path, kind)?;
+        let path = recorder.path().to_path_buf();
+        self.recorder = Some(recorder);
+        Ok(path)
+    }
+
+    /// Stops recording and finishes the files, returning the path of the recording. Returns
+    /// `None` if nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Result<PathBuf, Box<dyn Error>>> {
+        self.recorder.take().map(Recorder::finish)
+    }
+
+    /// What is being recorded, if anything.
+    pub fn recording(&self) -> Option<RecordingKind> {
+        self.recorder.as_ref().map(Recorder::kind)
     }
 
     pub fn pixels(&self) -> &[u8] {
         &self.pixels
     }
 
+    /// The width of the screen in pixels, which is wider than the NES screen with the NTSC
+    /// filter on. The height is always `SCREEN_HEIGHT`.
+    pub fn frame_width(&self) -> usize {
+        self.pixels.len() / (SCREEN_HEIGHT * 4)
+    }
+
     pub fn key_down(&mut self, key_code: KeyCode) {
-        self.set_button_pressed(key_code, true);
+        self.set_key_pressed(key_code, true);
     }
 
     pub fn key_up(&mut self, key_code: KeyCode) {
-        self.set_button_pressed(key_code, false);
+        self.set_key_pressed(key_code, false);
+    }
+
+    /// Switches to a different audio output, restarting the emulation clock from the new
+    /// output's time source.
+    pub fn set_audio_output(&mut self, audio: AudioOutput) {
+        self.time_source = audio.time_source();
+        self.audio = audio;
+        self.resync_time();
+    }
+
+    pub fn set_keymap(&mut self, keymap: HashMap<KeyCode, (Port, Button)>) {
+        self.keymap = keymap;
+    }
+
+    /// Sets the colours frames are drawn with, from the next frame on.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Turns the NTSC filter on with the given preset, or off. The screen changes size from the
+    /// next frame on.
+    pub fn set_ntsc_filter(&mut self, preset: Option<NtscPreset>) {
+        if self.ntsc.as_ref().map(NtscFilter::preset) != preset {
+            self.ntsc = preset.map(NtscFilter::new);
+        }
     }
 
-    fn set_button_pressed(&mut self, key_code: KeyCode, pressed: bool) {
-        if let Some(button) = self.keymap.get(&key_code) {
-            self.nes
-                .interconnect
-                .input
-                .game_pad_1
-                .set_button_pressed(*button, pressed)
+    fn set_key_pressed(&mut self, key_code: KeyCode, pressed: bool) {
+        if let Some(&(port, button)) = self.keymap.get(&key_code) {
+            self.set_button_pressed(InputSource::Keyboard, port, button, pressed);
+        }
+    }
+
+    pub fn set_button_pressed(
+        &mut self,
+        source: InputSource,
+        port: Port,
+        button: Button,
+        pressed: bool,
+    ) {
+        let bit = movie::button_bit(button);
+        let buttons = &mut self.source_buttons.entry(source).or_default()[port.index()];
+        if pressed {
+            *buttons |= bit;
+        } else {
+            *buttons &= !bit;
+        }
+        self.update_held_buttons();
+    }
+
+    /// Lets go of every button a controller is holding, for when it's unplugged or moved to
+    /// another port.
+    pub fn release_gamepad(&mut self, id: GamepadId) {
+        self.source_buttons.retain(|source, _| match source {
+            InputSource::Keyboard => true,
+            InputSource::GamepadButtons(source_id) | InputSource::GamepadAxis(source_id, _) => {
+                *source_id != id
+            }
+        });
+        self.update_held_buttons();
+    }
+
+    fn update_held_buttons(&mut self) {
+        self.held_buttons = self.source_buttons.values().fold([0; 2], |held, buttons| {
+            [held[0] | buttons[0], held[1] | buttons[1]]
+        });
+
+        if self.movie.is_none() {
+            self.apply_buttons(self.held_buttons);
         }
     }
 
     pub fn rom_path(&self) -> &Path {
         &self.rom_path
     }
+
+    pub fn rom_info(&self) -> &RomInfo {
+        &self.rom_info
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.flush_sram();
+    }
 }
 
-pub fn load_rom(filename: &Path) -> Result<Cartridge, Box<dyn Error>> {
-    let file = File::open(filename)?;
+pub fn set_button_pressed(nes: &mut Nes, port: Port, button: Button, pressed: bool) {
+    let input = &mut nes.interconnect.input;
+    let game_pad = match port {
+        Port::One => &mut input.game_pad_1,
+        Port::Two => &mut input.game_pad_2,
+    };
+    game_pad.set_button_pressed(button, pressed);
+}
+
+/// The PPU's colour emphasis bits, red, green and blue from the lowest bit. They're read before
+/// each instruction, so a frame is drawn with the emphasis set when it finished.
+pub fn emphasis(nes: &Nes) -> u8 {
+    nes.interconnect.ppu.emphasis()
+}
+
+/// Save states are kept in the XDG data directory, falling back to the ROM's directory if it
+/// can't be determined or created. They're named after the ROM and the checksum of its contents,
+/// so ROMs that share a file name, or a patched and unpatched copy of one, don't share slots.
+fn state_path(rom_path: &Path, checksums: &Checksums) -> PathBuf {
+    let stem = rom_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = format!("{}-{:08X}.nes", stem, checksums.crc32);
+    let state_dir = dirs::data_dir().map(|dir| dir.join("rustednes-cosmic").join("states"));
+
+    match state_dir {
+        Some(state_dir) => match fs::create_dir_all(&state_dir) {
+            Ok(()) => state_dir.join(file_name),
+            Err(err) => {
+                tracing::warn!("failed to create {}: {}", state_dir.display(), err);
+                rom_path.with_file_name(file_name)
+            }
+        },
+        None => rom_path.with_file_name(file_name),
+    }
+}
 
-    let cartridge = match filename.extension() {
-        Some(ext) if ext == "zip" => {
-            tracing::info!("Unzipping {}", filename.display());
-            let mut zip = zip::ZipArchive::new(&file)?;
-            let mut zip_file = zip.by_index(0)?;
-            Cartridge::load(&mut zip_file)?
+#[derive(Debug)]
+pub enum RomLoadError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    #[cfg(feature = "7z")]
+    SevenZip(sevenz_rust::Error),
+    NoRomInArchive,
+    UnsupportedMapper(u16),
+    BadHeader(InvalidHeader),
+    /// The core couldn't make a cartridge out of the ROM.
+    Cartridge(LoadError),
+    Patch(PatchError),
+}
+
+impl fmt::Display for RomLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomLoadError::Io(err) => write!(f, "{err}"),
+            RomLoadError::Zip(err) => write!(f, "invalid zip archive: {err}"),
+            #[cfg(feature = "7z")]
+            RomLoadError::SevenZip(err) => write!(f, "invalid 7z archive: {err}"),
+            RomLoadError::NoRomInArchive => write!(f, "no NES ROM found in archive"),
+            RomLoadError::UnsupportedMapper(mapper) => write!(f, "unsupported mapper {mapper}"),
+            RomLoadError::BadHeader(err) => write!(f, "bad ROM header: {err}"),
+            RomLoadError::Cartridge(err) => write!(f, "invalid ROM: {err}"),
+            RomLoadError::Patch(err) => write!(f, "couldn't apply patch: {err}"),
         }
-        _ => {
-            let mut file = file;
-            Cartridge::load(&mut file)?
+    }
+}
+
+impl Error for RomLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RomLoadError::Io(err) => Some(err),
+            RomLoadError::Zip(err) => Some(err),
+            #[cfg(feature = "7z")]
+            RomLoadError::SevenZip(err) => Some(err),
+            RomLoadError::BadHeader(err) => Some(err),
+            RomLoadError::Cartridge(err) => Some(err),
+            RomLoadError::Patch(err) => Some(err),
+            RomLoadError::NoRomInArchive | RomLoadError::UnsupportedMapper(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomLoadError {
+    fn from(err: io::Error) -> Self {
+        RomLoadError::Io(err)
+    }
+}
+
+impl From<zip::result::ZipError> for RomLoadError {
+    fn from(err: zip::result::ZipError) -> Self {
+        RomLoadError::Zip(err)
+    }
+}
+
+#[cfg(feature = "7z")]
+impl From<sevenz_rust::Error> for RomLoadError {
+    fn from(err: sevenz_rust::Error) -> Self {
+        RomLoadError::SevenZip(err)
+    }
+}
+
+impl From<PatchError> for RomLoadError {
+    fn from(err: PatchError) -> Self {
+        RomLoadError::Patch(err)
+    }
+}
+
+impl From<InvalidHeader> for RomLoadError {
+    fn from(err: InvalidHeader) -> Self {
+        RomLoadError::BadHeader(err)
+    }
+}
+
+/// Loads a ROM file. For archives, `entry` names the file inside it to load, otherwise the first
+/// NES ROM found in the archive is used.
+///
+/// The ROM is soft-patched in memory with `patch_path`, or if that isn't given, with a patch
+/// next to the ROM that has the same name. The files on disk are never modified. A patch that
+/// was found next to the ROM but can't be applied is skipped, and the ROM is loaded unpatched.
+pub fn load_rom(
+    filename: &Path,
+    entry: Option<&str>,
+    patch_path: Option<&Path>,
+) -> Result<Rom, RomLoadError> {
+    let mut data = read_rom_data(filename, entry)?;
+
+    let mut skipped_patch = None;
+    let patch_path = match patch_path {
+        Some(patch_path) => {
+            apply_patch(&mut data, patch_path)?;
+            Some(patch_path.to_path_buf())
         }
+        None => match patch::find_patch(filename) {
+            Some(found) => match apply_patch(&mut data, &found) {
+                Ok(()) => Some(found),
+                Err(err) => {
+                    tracing::warn!("Skipping patch {}: {}", found.display(), err);
+                    skipped_patch = Some(found);
+                    None
+                }
+            },
+            None => None,
+        },
     };
 
-    Ok(cartridge)
+    let mut rom = parse_rom(data)?;
+    rom.info.entry = entry.map(str::to_string);
+    rom.info.patch = patch_path;
+    rom.info.skipped_patch = skipped_patch;
+    Ok(rom)
+}
+
+fn apply_patch(data: &mut Vec<u8>, patch_path: &Path) -> Result<(), RomLoadError> {
+    tracing::info!("Applying patch {}", patch_path.display());
+    patch::apply(data, patch_path, &fs::read(patch_path)?)?;
+    Ok(())
+}
+
+fn read_rom_data(filename: &Path, entry: Option<&str>) -> Result<Vec<u8>, RomLoadError> {
+    match ArchiveKind::of(filename) {
+        Some(kind) => {
+            tracing::info!("Extracting {}", filename.display());
+            archive::read_rom(filename, kind, entry)
+        }
+        None => Ok(fs::read(filename)?),
+    }
+}
+
+/// Parses a ROM, fixing its header first if the game is in the ROM database and the header
+/// disagrees with it.
+fn parse_rom(mut data: Vec<u8>) -> Result<Rom, RomLoadError> {
+    let mut header = RomHeader::parse(&data)?;
+    let mut original_header = None;
+    let checksums = header.checksums(&data);
+    let game = romdb::lookup(checksums.crc32).cloned();
+    if let Some(game) = &game {
+        tracing::info!("Found {} in the ROM database", game.title);
+        if header.differs_from(game) {
+            tracing::info!("Correcting header: {:?} -> {:?}", header, game);
+            rom::correct_header(&mut data, &header, game);
+            original_header = Some(std::mem::replace(&mut header, RomHeader::parse(&data)?));
+        }
+    }
+
+    // Which mappers are supported is up to the core. The number it reports is left out, as
+    // only the header parsed here has the full NES 2.0 mapper number.
+    let cartridge = Cartridge::load(&mut Cursor::new(&data)).map_err(|err| match err {
+        LoadError::UnsupportedMapper(_) => RomLoadError::UnsupportedMapper(header.mapper),
+        LoadError::Io(err) => RomLoadError::Io(err),
+        err => RomLoadError::Cartridge(err),
+    })?;
+
+    Ok(Rom {
+        cartridge,
+        info: RomInfo {
+            header,
+            original_header,
+            checksums,
+            entry: None,
+            patch: None,
+            skipped_patch: None,
+            game,
+        },
+    })
 }
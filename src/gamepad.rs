@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Game controller input, read from gilrs on a background thread.
+//!
+//! Events are translated into [`GamepadEvent`] so the rest of the app doesn't depend on gilrs,
+//! and [`events`] can stream them from any other source in place of real hardware.
+
+use crate::keymap::NesButton;
+use cosmic::iced::{stream, Subscription};
+use futures_util::{SinkExt, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::mpsc;
+
+/// Stick deflection needed before it counts as a d-pad press.
+pub const AXIS_THRESHOLD: f32 = 0.5;
+
+/// Per-device button mappings, keyed by the NES button they trigger.
+pub type GamepadMapping = BTreeMap<NesButton, GamepadButton>;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct GamepadId(pub usize);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GamepadInfo {
+    pub name: String,
+    /// Identifies the model of controller, used as the key for stored mappings.
+    pub uuid: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    DPadX,
+    DPadY,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId, GamepadInfo),
+    Disconnected(GamepadId),
+    Button(GamepadId, GamepadButton, bool),
+    Axis(GamepadId, GamepadAxis, f32),
+}
+
+pub fn default_mapping() -> GamepadMapping {
+    BTreeMap::from([
+        (NesButton::A, GamepadButton::East),
+        (NesButton::B, GamepadButton::South),
+        (NesButton::Select, GamepadButton::Select),
+        (NesButton::Start, GamepadButton::Start),
+        (NesButton::Up, GamepadButton::DPadUp),
+        (NesButton::Down, GamepadButton::DPadDown),
+        (NesButton::Left, GamepadButton::DPadLeft),
+        (NesButton::Right, GamepadButton::DPadRight),
+    ])
+}
+
+/// Finds the NES button a gamepad button is mapped to.
+pub fn mapped_button(mapping: &GamepadMapping, button: GamepadButton) -> Option<NesButton> {
+    mapping
+        .iter()
+        .find(|(_, mapped)| **mapped == button)
+        .map(|(nes_button, _)| *nes_button)
+}
+
+/// The direction an axis position presses, if it's pushed far enough.
+pub fn axis_direction(axis: GamepadAxis, value: f32) -> Option<NesButton> {
+    let (negative, positive) = match axis {
+        GamepadAxis::LeftStickX | GamepadAxis::DPadX => (NesButton::Left, NesButton::Right),
+        // Positive Y points up in gilrs.
+        GamepadAxis::LeftStickY | GamepadAxis::DPadY => (NesButton::Down, NesButton::Up),
+    };
+
+    if value <= -AXIS_THRESHOLD {
+        Some(negative)
+    } else if value >= AXIS_THRESHOLD {
+        Some(positive)
+    } else {
+        None
+    }
+}
+
+/// A change in the direction an axis is pushed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AxisChange {
+    pub released: Option<NesButton>,
+    pub pressed: Option<NesButton>,
+}
+
+/// The direction each axis of a controller is pushed in, so that only crossing the threshold is
+/// passed on and stick noise around the centre is ignored.
+#[derive(Clone, Debug, Default)]
+pub struct AxisState {
+    directions: HashMap<GamepadAxis, NesButton>,
+}
+
+impl AxisState {
+    /// Moves an axis, returning the change in direction if it crossed the threshold.
+    pub fn update(&mut self, axis: GamepadAxis, value: f32) -> Option<AxisChange> {
+        let pressed = axis_direction(axis, value);
+        let released = match pressed {
+            Some(direction) => self.directions.insert(axis, direction),
+            None => self.directions.remove(&axis),
+        };
+
+        (released != pressed).then_some(AxisChange { released, pressed })
+    }
+}
+
+/// Watches for gamepad events, including controllers being plugged in and removed.
+pub fn subscription() -> Subscription<GamepadEvent> {
+    struct GamepadSubscription;
+
+    Subscription::run_with_id(
+        std::any::TypeId::of::<GamepadSubscription>(),
+        events(poll_gilrs),
+    )
+}
+
+/// Streams the events `source` sends, running it on its own thread until it returns.
+pub fn events<F>(source: F) -> impl Stream<Item = GamepadEvent>
+where
+    F: FnOnce(mpsc::Sender<GamepadEvent>) + Send + 'static,
+{
+    stream::channel(32, |mut output| async move {
+        let (sender, mut receiver) = mpsc::channel(32);
+
+        // Gilrs isn't `Send` on every platform, so it lives on its own thread for the lifetime
+        // of the app.
+        std::thread::spawn(move || source(sender));
+
+        while let Some(event) = receiver.recv().await {
+            if output.send(event).await.is_err() {
+                break;
+            }
+        }
+
+        futures_util::future::pending::<()>().await;
+    })
+}
+
+fn poll_gilrs(sender: mpsc::Sender<GamepadEvent>) {
+    let mut gilrs = match gilrs::Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(err) => {
+            tracing::error!("failed to initialize gamepad support: {}", err);
+            return;
+        }
+    };
+
+    // Controllers that were plugged in before startup don't get a connected event.
+    for (id, gamepad) in gilrs.gamepads() {
+        let event = GamepadEvent::Connected(GamepadId(id.into()), gamepad_info(&gamepad));
+        if sender.blocking_send(event).is_err() {
+            return;
+        }
+    }
+
+    while let Some(gilrs::Event { id, event, .. }) = gilrs.next_event_blocking(None) {
+        let gamepad_id = GamepadId(id.into());
+        let event = match event {
+            gilrs::EventType::Connected => {
+                GamepadEvent::Connected(gamepad_id, gamepad_info(&gilrs.gamepad(id)))
+            }
+            gilrs::EventType::Disconnected => GamepadEvent::Disconnected(gamepad_id),
+            gilrs::EventType::ButtonPressed(button, _) => match convert_button(button) {
+                Some(button) => GamepadEvent::Button(gamepad_id, button, true),
+                None => continue,
+            },
+            gilrs::EventType::ButtonReleased(button, _) => match convert_button(button) {
+                Some(button) => GamepadEvent::Button(gamepad_id, button, false),
+                None => continue,
+            },
+            gilrs::EventType::AxisChanged(axis, value, _) => match convert_axis(axis) {
+                Some(axis) => GamepadEvent::Axis(gamepad_id, axis, value),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        if sender.blocking_send(event).is_err() {
+            return;
+        }
+    }
+}
+
+fn gamepad_info(gamepad: &gilrs::Gamepad) -> GamepadInfo {
+    GamepadInfo {
+        name: gamepad.name().to_string(),
+        uuid: gamepad
+            .uuid()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+    }
+}
+
+fn convert_button(button: gilrs::Button) -> Option<GamepadButton> {
+    Some(match button {
+        gilrs::Button::South => GamepadButton::South,
+        gilrs::Button::East => GamepadButton::East,
+        gilrs::Button::North => GamepadButton::North,
+        gilrs::Button::West => GamepadButton::West,
+        gilrs::Button::LeftTrigger => GamepadButton::LeftTrigger,
+        gilrs::Button::LeftTrigger2 => GamepadButton::LeftTrigger2,
+        gilrs::Button::RightTrigger => GamepadButton::RightTrigger,
+        gilrs::Button::RightTrigger2 => GamepadButton::RightTrigger2,
+        gilrs::Button::Select => GamepadButton::Select,
+        gilrs::Button::Start => GamepadButton::Start,
+        gilrs::Button::Mode => GamepadButton::Mode,
+        gilrs::Button::LeftThumb => GamepadButton::LeftThumb,
+        gilrs::Button::RightThumb => GamepadButton::RightThumb,
+        gilrs::Button::DPadUp => GamepadButton::DPadUp,
+        gilrs::Button::DPadDown => GamepadButton::DPadDown,
+        gilrs::Button::DPadLeft => GamepadButton::DPadLeft,
+        gilrs::Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+fn convert_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    Some(match axis {
+        gilrs::Axis::LeftStickX => GamepadAxis::LeftStickX,
+        gilrs::Axis::LeftStickY => GamepadAxis::LeftStickY,
+        gilrs::Axis::DPadX => GamepadAxis::DPadX,
+        gilrs::Axis::DPadY => GamepadAxis::DPadY,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn streams_events_from_source() {
+        let id = GamepadId(0);
+        let info = GamepadInfo {
+            name: "Virtual Pad".to_string(),
+            uuid: "0000".to_string(),
+        };
+        let script = vec![
+            GamepadEvent::Connected(id, info),
+            GamepadEvent::Button(id, GamepadButton::South, true),
+            GamepadEvent::Axis(id, GamepadAxis::LeftStickX, -1.0),
+            GamepadEvent::Button(id, GamepadButton::South, false),
+            GamepadEvent::Disconnected(id),
+        ];
+
+        let source_script = script.clone();
+        let received: Vec<_> = events(move |sender| {
+            for event in source_script {
+                sender.blocking_send(event).unwrap();
+            }
+        })
+        .take(script.len())
+        .collect()
+        .await;
+
+        assert_eq!(received, script);
+    }
+
+    #[test]
+    fn axis_changes_only_when_crossing_threshold() {
+        let mut state = AxisState::default();
+        let axis = GamepadAxis::LeftStickX;
+
+        assert_eq!(state.update(axis, 0.1), None);
+        assert_eq!(state.update(axis, -0.2), None);
+        assert_eq!(
+            state.update(axis, 0.9),
+            Some(AxisChange {
+                released: None,
+                pressed: Some(NesButton::Right),
+            })
+        );
+        assert_eq!(state.update(axis, 0.7), None);
+        assert_eq!(
+            state.update(axis, -0.8),
+            Some(AxisChange {
+                released: Some(NesButton::Right),
+                pressed: Some(NesButton::Left),
+            })
+        );
+        assert_eq!(
+            state.update(axis, 0.05),
+            Some(AxisChange {
+                released: Some(NesButton::Left),
+                pressed: None,
+            })
+        );
+        assert_eq!(state.update(axis, -0.05), None);
+    }
+
+    #[test]
+    fn axes_are_tracked_separately() {
+        let mut state = AxisState::default();
+
+        state.update(GamepadAxis::LeftStickX, 1.0);
+        assert_eq!(
+            state.update(GamepadAxis::LeftStickY, 1.0),
+            Some(AxisChange {
+                released: None,
+                pressed: Some(NesButton::Up),
+            })
+        );
+        assert_eq!(state.update(GamepadAxis::LeftStickX, 1.0), None);
+    }
+}
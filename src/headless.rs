@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Runs a ROM without a window or sound, for automated testing on machines without either.
+//!
+//! Emulation isn't tied to any clock, so it runs as fast as the host allows and the result only
+//! depends on the ROM and the input script.
+
+use crate::audio::NullAudioSink;
+use crate::emulator::{self, load_rom, FRAMES_PER_SECOND};
+use crate::keymap::{NesButton, Port};
+use crate::palette::Palette;
+use crate::video::VideoFrameSink;
+use rustednes_core::nes::Nes;
+use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How long to run for when neither a frame count nor a duration is given.
+const DEFAULT_FRAMES: u64 = 600;
+
+pub struct HeadlessOptions {
+    pub rom_path: PathBuf,
+    pub patch_path: Option<PathBuf>,
+    pub frames: Option<u64>,
+    pub seconds: Option<f64>,
+    pub input_script: Option<PathBuf>,
+}
+
+/// The buttons held on one controller from `frame` onwards.
+struct InputEvent {
+    frame: u64,
+    port: Port,
+    buttons: Vec<NesButton>,
+}
+
+/// Runs the ROM for the requested number of frames and prints the SHA-1 of the last frame.
+pub fn run(options: HeadlessOptions) -> Result<(), Box<dyn Error>> {
+    let rom = load_rom(&options.rom_path, None, options.patch_path.as_deref())?;
+    let mut nes = Nes::new(rom.cartridge);
+
+    let frames = match (options.frames, options.seconds) {
+        (Some(frames), _) => frames,
+        (None, Some(seconds)) => (seconds * FRAMES_PER_SECOND).round() as u64,
+        (None, None) => DEFAULT_FRAMES,
+    };
+    let mut events = match &options.input_script {
+        Some(path) => parse_input_script(path)?,
+        None => Vec::new(),
+    }
+    .into_iter()
+    .peekable();
+
+    let mut pixels = vec![0u8; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+    // Always the default palette, so that the hash doesn't depend on the user's settings.
+    let palette = Palette::default();
+    for frame in 0..frames {
+        while let Some(event) = events.next_if(|event| event.frame <= frame) {
+            for button in NesButton::ALL {
+                let pressed = event.buttons.contains(&button);
+                emulator::set_button_pressed(&mut nes, event.port, button.button(), pressed);
+            }
+        }
+
+        loop {
+            let mut video_sink =
+                VideoFrameSink::new(&mut pixels, &palette, None, emulator::emphasis(&nes));
+            nes.step(&mut video_sink, &mut NullAudioSink);
+            if video_sink.frame_written() {
+                break;
+            }
+        }
+    }
+
+    let hash: String = Sha1::digest(&pixels)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    println!("{hash}");
+
+    Ok(())
+}
+
+/// Reads an input script. Each line holds a frame number, a player number and the buttons that
+/// player holds from that frame on, joined with `+`, or `-` for none:
+///
+/// ```text
+/// # Press start, then run right while holding B.
+/// 60 1 Start
+/// 62 1 -
+/// 100 1 Right+B
+/// ```
+fn parse_input_script(path: &Path) -> Result<Vec<InputEvent>, Box<dyn Error>> {
+    let script = fs::read_to_string(path)?;
+    let mut events = Vec::new();
+
+    for (line_number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let event = parse_input_line(line).ok_or_else(|| {
+            format!(
+                "{}:{}: expected `<frame> <player> <buttons>`",
+                path.display(),
+                line_number + 1
+            )
+        })?;
+        events.push(event);
+    }
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+fn parse_input_line(line: &str) -> Option<InputEvent> {
+    let mut fields = line.split_whitespace();
+    let frame = fields.next()?.parse().ok()?;
+    let port = match fields.next()? {
+        "1" => Port::One,
+        "2" => Port::Two,
+        _ => return None,
+    };
+    let buttons = match fields.next()? {
+        "-" => Vec::new(),
+        buttons => buttons
+            .split('+')
+            .map(|name| {
+                NesButton::ALL
+                    .into_iter()
+                    .find(|button| format!("{button:?}").eq_ignore_ascii_case(name))
+            })
+            .collect::<Option<_>>()?,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(InputEvent {
+        frame,
+        port,
+        buttons,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_input_line() {
+        let event = parse_input_line("100 2 Right+b").unwrap();
+        assert_eq!(event.frame, 100);
+        assert_eq!(event.port, Port::Two);
+        assert_eq!(event.buttons, [NesButton::Right, NesButton::B]);
+
+        let event = parse_input_line("62\t1  -").unwrap();
+        assert_eq!(event.frame, 62);
+        assert_eq!(event.port, Port::One);
+        assert!(event.buttons.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_input_lines() {
+        for line in [
+            "60",
+            "60 1",
+            "sixty 1 Start",
+            "60 3 Start",
+            "60 1 Turbo",
+            "60 1 Start+",
+            "60 1 Start B",
+        ] {
+            assert!(parse_input_line(line).is_none(), "{line}");
+        }
+    }
+
+    #[test]
+    fn parses_input_script_in_frame_order() {
+        let path = std::env::temp_dir().join(format!("rustednes-input-{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            "# Press start, then run right while holding B.\n\
+             100 1 Right+B # run\n\
+             \n\
+             60 1 Start\n\
+             62 1 -\n",
+        )
+        .unwrap();
+        let events = parse_input_script(&path);
+        fs::remove_file(&path).unwrap();
+
+        let frames: Vec<_> = events.unwrap().iter().map(|event| event.frame).collect();
+        assert_eq!(frames, [60, 62, 100]);
+    }
+
+    #[test]
+    fn reports_line_of_invalid_input() {
+        let path = std::env::temp_dir().join(format!("rustednes-bad-{}.txt", std::process::id()));
+        fs::write(&path, "60 1 Start\n# comment\n62 1 Jump\n").unwrap();
+        let result = parse_input_script(&path);
+        fs::remove_file(&path).unwrap();
+
+        let err = result.err().unwrap().to_string();
+        assert!(
+            err.ends_with(":3: expected `<frame> <player> <buttons>`"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Keyboard bindings for the NES controller buttons on both controller ports.
+
+use cosmic::iced::keyboard::key::Code as KeyCode;
+use rustednes_core::input::Button;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Bindings as stored in the config, keyed by button with the key's code name as the value.
+pub type KeyBindings = BTreeMap<NesButton, String>;
+
+/// The controller port a binding applies to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub enum Port {
+    One,
+    Two,
+}
+
+impl Port {
+    pub const ALL: [Port; 2] = [Port::One, Port::Two];
+
+    pub fn index(self) -> usize {
+        match self {
+            Port::One => 0,
+            Port::Two => 1,
+        }
+    }
+}
+
+/// A serializable mirror of [`Button`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+pub enum NesButton {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl NesButton {
+    pub const ALL: [NesButton; 8] = [
+        NesButton::A,
+        NesButton::B,
+        NesButton::Select,
+        NesButton::Start,
+        NesButton::Up,
+        NesButton::Down,
+        NesButton::Left,
+        NesButton::Right,
+    ];
+
+    pub fn button(self) -> Button {
+        match self {
+            NesButton::A => Button::A,
+            NesButton::B => Button::B,
+            NesButton::Select => Button::Select,
+            NesButton::Start => Button::Start,
+            NesButton::Up => Button::Up,
+            NesButton::Down => Button::Down,
+            NesButton::Left => Button::Left,
+            NesButton::Right => Button::Right,
+        }
+    }
+}
+
+pub fn default_key_bindings(port: Port) -> KeyBindings {
+    let keys = match port {
+        Port::One => [
+            (NesButton::A, KeyCode::KeyX),
+            (NesButton::B, KeyCode::KeyZ),
+            (NesButton::Select, KeyCode::Space),
+            (NesButton::Start, KeyCode::Enter),
+            (NesButton::Up, KeyCode::ArrowUp),
+            (NesButton::Down, KeyCode::ArrowDown),
+            (NesButton::Left, KeyCode::ArrowLeft),
+            (NesButton::Right, KeyCode::ArrowRight),
+        ],
+        Port::Two => [
+            (NesButton::A, KeyCode::KeyH),
+            (NesButton::B, KeyCode::KeyG),
+            (NesButton::Select, KeyCode::KeyT),
+            (NesButton::Start, KeyCode::KeyY),
+            (NesButton::Up, KeyCode::KeyW),
+            (NesButton::Down, KeyCode::KeyS),
+            (NesButton::Left, KeyCode::KeyA),
+            (NesButton::Right, KeyCode::KeyD),
+        ],
+    };
+
+    keys.into_iter()
+        .map(|(button, key_code)| (button, key_code_name(key_code)))
+        .collect()
+}
+
+/// Builds the lookup table used by the emulator from the bindings of each port, skipping any key
+/// names that aren't recognized.
+pub fn keymap(bindings: &[KeyBindings; 2]) -> HashMap<KeyCode, (Port, Button)> {
+    Port::ALL
+        .into_iter()
+        .flat_map(|port| {
+            bindings[port.index()]
+                .iter()
+                .filter_map(move |(button, name)| {
+                    Some((parse_key_code(name)?, (port, button.button())))
+                })
+        })
+        .collect()
+}
+
+/// Binds `key_code` to `button` on `port`, removing it from any button it was previously bound
+/// to on either port. Returns `false` if the key can't be used as a binding.
+pub fn bind(
+    bindings: &mut [KeyBindings; 2],
+    port: Port,
+    button: NesButton,
+    key_code: KeyCode,
+) -> bool {
+    if !KEY_CODES.contains(&key_code) {
+        return false;
+    }
+
+    let name = key_code_name(key_code);
+    for port_bindings in bindings.iter_mut() {
+        port_bindings.retain(|_, bound| *bound != name);
+    }
+    bindings[port.index()].insert(button, name);
+    true
+}
+
+pub fn key_code_name(key_code: KeyCode) -> String {
+    format!("{key_code:?}")
+}
+
+pub fn parse_key_code(name: &str) -> Option<KeyCode> {
+    KEY_CODES
+        .iter()
+        .copied()
+        .find(|key_code| key_code_name(*key_code) == name)
+}
+
+/// A short human readable name for a key, e.g. `X` instead of `KeyX`.
+pub fn key_label(name: &str) -> &str {
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(name)
+}
+
+/// Keys that can be bound to controller buttons. Escape cancels rebinding, and the keys used for
+/// rewinding and fast-forwarding are left out so they can't be shadowed.
+const KEY_CODES: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::NumpadAdd,
+    KeyCode::NumpadSubtract,
+    KeyCode::NumpadMultiply,
+    KeyCode::NumpadDivide,
+    KeyCode::NumpadDecimal,
+    KeyCode::NumpadEnter,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Backquote,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+];
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Scans the ROM directory for games to show in the library.
+
+use crate::archive::{self, ArchiveKind};
+use crate::rom::{self, RomHeader, INES_HEADER_LEN};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct LibraryEntry {
+    pub path: PathBuf,
+    pub title: String,
+    pub mapper: Option<u16>,
+    pub size: u64,
+}
+
+impl LibraryEntry {
+    pub fn matches(&self, filter: &str) -> bool {
+        filter.is_empty() || self.title.to_lowercase().contains(&filter.to_lowercase())
+    }
+}
+
+/// Recursively finds all ROM files and archives below `dir`, sorted by title.
+pub fn scan(dir: &Path) -> Vec<LibraryEntry> {
+    // Paths are canonicalized so they line up with the keys used for last played times.
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut entries = Vec::new();
+    scan_dir(&dir, &mut entries);
+    entries.sort_by_cached_key(|entry| entry.title.to_lowercase());
+    entries
+}
+
+fn scan_dir(dir: &Path, entries: &mut Vec<LibraryEntry>) {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) => {
+            tracing::warn!("failed to read {}: {}", dir.display(), err);
+            return;
+        }
+    };
+
+    for dir_entry in read_dir.flatten() {
+        let path = dir_entry.path();
+        let Ok(file_type) = dir_entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_dir() {
+            scan_dir(&path, entries);
+        } else if is_rom_file(&path) {
+            let size = dir_entry
+                .metadata()
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            let title = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            entries.push(LibraryEntry {
+                mapper: read_header(&path).map(|header| header.mapper),
+                path,
+                title,
+                size,
+            });
+        }
+    }
+}
+
+pub fn is_rom_file(path: &Path) -> bool {
+    ArchiveKind::of(path).is_some()
+        || path.extension().is_some_and(|ext| {
+            rom::EXTENSIONS
+                .iter()
+                .any(|rom_ext| ext.eq_ignore_ascii_case(rom_ext))
+        })
+}
+
+/// Reads the iNES header of a ROM, looking inside archives.
+fn read_header(path: &Path) -> Option<RomHeader> {
+    if let Some(kind) = ArchiveKind::of(path) {
+        let data = archive::read_rom(path, kind, None).ok()?;
+        return RomHeader::parse(&data).ok();
+    }
+
+    let mut header = [0u8; INES_HEADER_LEN];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    RomHeader::parse(&header).ok()
+}
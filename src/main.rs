@@ -1,20 +1,34 @@
 // SPDX-License-Identifier: MPL-2.0
 
 mod app;
+mod archive;
 mod audio;
+mod capture;
 mod config;
 mod emulator;
+mod gamepad;
+mod headless;
 mod i18n;
+mod keymap;
+mod library;
+mod movie;
+mod ntsc;
+mod palette;
+mod patch;
+mod recording;
+mod rewind;
+mod rom;
+mod romdb;
+mod screenshot;
+mod sram;
 mod video;
 
 use clap::Parser;
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use cosmic::iced::Size;
-use emulator::load_rom;
 use rustednes_common::logger;
 use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use std::{error::Error, path::PathBuf};
-use tracing::info;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,6 +37,34 @@ struct Opt {
     #[arg(name = "ROM")]
     rom_path: Option<PathBuf>,
 
+    /// An IPS, UPS or BPS patch to apply to the ROM, instead of one next to it with the same name
+    #[arg(long, requires = "ROM")]
+    patch: Option<PathBuf>,
+
+    /// Where to send sound, overriding the setting
+    #[arg(long, value_enum, value_name = "BACKEND")]
+    audio: Option<audio::AudioBackend>,
+
+    /// The file written to by the WAV audio backend, replacing it if it exists
+    #[arg(long, value_name = "PATH")]
+    audio_file: Option<PathBuf>,
+
+    /// Run the ROM without a window or sound and print a hash of the last frame
+    #[arg(long, requires = "ROM")]
+    headless: bool,
+
+    /// Number of frames to run for in headless mode [default: 600]
+    #[arg(long, requires = "headless", conflicts_with = "seconds")]
+    frames: Option<u64>,
+
+    /// Number of seconds of emulated time to run for in headless mode
+    #[arg(long, requires = "headless")]
+    seconds: Option<f64>,
+
+    /// A script of controller input to play back in headless mode
+    #[arg(long, requires = "headless")]
+    input: Option<PathBuf>,
+
     #[clap(flatten)]
     verbose: Verbosity<InfoLevel>,
 }
@@ -32,6 +74,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     logger::initialize(&opt.verbose);
 
+    if opt.headless {
+        return headless::run(headless::HeadlessOptions {
+            rom_path: opt.rom_path.ok_or("--headless requires a ROM")?,
+            patch_path: opt.patch,
+            frames: opt.frames,
+            seconds: opt.seconds,
+            input_script: opt.input,
+        });
+    }
+
     // Get the system's preferred languages.
     let requested_languages = i18n_embed::DesktopLanguageRequester::requested_languages();
 
@@ -52,16 +104,15 @@ fn main() -> Result<(), Box<dyn Error>> {
             SCREEN_HEIGHT as f32 * 3.0 + titlebar_height,
         ));
 
-    let rom = if let Some(rom_path) = &opt.rom_path {
-        let rom = load_rom(&rom_path)?;
-        info!("{:?}", rom);
-        let rom_path = rom_path.to_path_buf();
-        Some((rom, rom_path))
-    } else {
-        None
+    // The ROM is loaded by the app so that load errors can be shown in the window. If the app is
+    // already running, the ROM is passed along to it instead.
+    let flags = app::Flags {
+        audio_backend: opt.audio,
+        audio_file: opt.audio_file,
+        ..app::Flags::new(opt.rom_path, opt.patch)
     };
 
-    cosmic::app::run::<app::AppModel>(settings, app::Flags { rom })?;
+    cosmic::app::run_single_instance::<app::AppModel>(settings, flags)?;
 
     Ok(())
 }
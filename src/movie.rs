@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Input movies in FCEUX's FM2 format, which log the controller state of every frame so that a
+//! run can be played back exactly.
+//!
+//! Movies that start from a save state keep it in a `rustednesState` header. Other emulators
+//! ignore the header, so only movies recorded from power on play back in them.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use md5::{Digest, Md5};
+use rustednes_core::input::Button;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// FM2 button letters, from the highest bit of a controller's buttons to the lowest.
+const BUTTON_LETTERS: &[u8; 8] = b"RLDUTSBA";
+
+/// A soft reset, pressing the console's reset button before the frame.
+pub const COMMAND_RESET: u8 = 1;
+/// A hard reset, switching the console off and on again before the frame.
+pub const COMMAND_POWER: u8 = 2;
+
+/// Where a new movie starts from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieStart {
+    PowerOn,
+    CurrentState,
+}
+
+/// The input for one frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: u8,
+    /// The buttons held on each controller, one bit per button as given by [`button_bit`].
+    pub buttons: [u8; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct Movie {
+    pub rom_filename: String,
+    /// MD5 of the PRG and CHR ROM the movie was recorded with.
+    pub rom_md5: [u8; 16],
+    pub guid: String,
+    pub rerecord_count: u32,
+    /// The serialized emulator state the movie starts from, or `None` to start from power on.
+    pub start_state: Option<Vec<u8>>,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(rom_filename: String, rom_md5: [u8; 16], start_state: Option<Vec<u8>>) -> Movie {
+        Movie {
+            rom_filename,
+            rom_md5,
+            guid: new_guid(&rom_md5),
+            rerecord_count: 0,
+            start_state,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, MovieError> {
+        parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "version 3")?;
+        writeln!(out, "emuVersion 0")?;
+        writeln!(out, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(out, "palFlag 0")?;
+        writeln!(out, "romFilename {}", self.rom_filename)?;
+        writeln!(out, "romChecksum base64:{}", BASE64.encode(self.rom_md5))?;
+        writeln!(out, "guid {}", self.guid)?;
+        writeln!(out, "fourscore 0")?;
+        writeln!(out, "microphone 0")?;
+        writeln!(out, "port0 1")?;
+        writeln!(out, "port1 1")?;
+        writeln!(out, "port2 0")?;
+        writeln!(out, "FDS 0")?;
+        writeln!(out, "NewPPU 0")?;
+        if let Some(state) = &self.start_state {
+            writeln!(out, "rustednesState base64:{}", BASE64.encode(state))?;
+        }
+
+        for frame in &self.frames {
+            writeln!(
+                out,
+                "|{}|{}|{}||",
+                frame.commands,
+                format_buttons(frame.buttons[0]),
+                format_buttons(frame.buttons[1])
+            )?;
+        }
+
+        out.flush()
+    }
+}
+
+/// The bit a button is stored in, matching the order of the letters in an FM2 input line.
+pub fn button_bit(button: Button) -> u8 {
+    match button {
+        Button::A => 0x01,
+        Button::B => 0x02,
+        Button::Select => 0x04,
+        Button::Start => 0x08,
+        Button::Up => 0x10,
+        Button::Down => 0x20,
+        Button::Left => 0x40,
+        Button::Right => 0x80,
+    }
+}
+
+/// Formats a controller's buttons the way FM2 does, e.g. `R.....B.` for right and B.
+pub fn format_buttons(buttons: u8) -> String {
+    BUTTON_LETTERS
+        .iter()
+        .enumerate()
+        .map(|(i, letter)| {
+            if buttons & (0x80 >> i) != 0 {
+                char::from(*letter)
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+fn parse_buttons(field: &str) -> Option<u8> {
+    if field.len() != BUTTON_LETTERS.len() {
+        return None;
+    }
+
+    // FM2 writers use either a space or a dot for buttons that aren't held.
+    Some(
+        field
+            .bytes()
+            .enumerate()
+            .filter(|(_, letter)| *letter != b' ' && *letter != b'.')
+            .fold(0, |buttons, (i, _)| buttons | (0x80 >> i)),
+    )
+}
+
+fn parse(text: &str) -> Result<Movie, MovieError> {
+    let mut movie = Movie {
+        rom_filename: String::new(),
+        rom_md5: [0; 16],
+        guid: String::new(),
+        rerecord_count: 0,
+        start_state: None,
+        frames: Vec::new(),
+    };
+
+    for (line_number, line) in text.lines().enumerate() {
+        let invalid = |reason| MovieError::Invalid {
+            line: line_number + 1,
+            reason,
+        };
+
+        if let Some(input) = line.strip_prefix('|') {
+            let mut fields = input.split('|');
+            let commands = fields
+                .next()
+                .and_then(|commands| commands.trim().parse().ok())
+                .ok_or_else(|| invalid("invalid commands"))?;
+            let mut buttons = [0; 2];
+            for port_buttons in &mut buttons {
+                *port_buttons = match fields.next() {
+                    // A movie recorded with the port unplugged has no field for it.
+                    None | Some("") => 0,
+                    Some(field) => {
+                        parse_buttons(field).ok_or_else(|| invalid("invalid buttons"))?
+                    }
+                };
+            }
+            movie.frames.push(MovieFrame { commands, buttons });
+            continue;
+        }
+
+        let (key, value) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match key {
+            "romFilename" => movie.rom_filename = value.to_string(),
+            "guid" => movie.guid = value.to_string(),
+            "rerecordCount" => {
+                movie.rerecord_count = value.parse().map_err(|_| invalid("invalid number"))?
+            }
+            "romChecksum" => {
+                movie.rom_md5 = decode_base64(value)
+                    .and_then(|md5| md5.try_into().ok())
+                    .ok_or_else(|| invalid("invalid ROM checksum"))?
+            }
+            "rustednesState" => {
+                movie.start_state =
+                    Some(decode_base64(value).ok_or_else(|| invalid("invalid save state"))?)
+            }
+            "palFlag" if value != "0" => return Err(invalid("PAL movies aren't supported")),
+            "fourscore" if value != "0" => {
+                return Err(invalid("Four Score movies aren't supported"))
+            }
+            "savestate" => {
+                return Err(invalid(
+                    "movies starting from an FCEUX save state aren't supported",
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(movie)
+}
+
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    BASE64.decode(value.strip_prefix("base64:")?).ok()
+}
+
+/// Makes an identifier for a new movie in the GUID format FCEUX uses.
+fn new_guid(rom_md5: &[u8; 16]) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let hash = Md5::new()
+        .chain_update(rom_md5)
+        .chain_update(now.to_le_bytes())
+        .finalize();
+    let hex: String = hash.iter().map(|byte| format!("{byte:02X}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    Invalid { line: usize, reason: &'static str },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(err) => write!(f, "{err}"),
+            MovieError::Invalid { line, reason } => write!(f, "line {line}: {reason}"),
+        }
+    }
+}
+
+impl Error for MovieError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MovieError::Io(err) => Some(err),
+            MovieError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOVIE: &str = "\
+version 3
+emuVersion 22020
+rerecordCount 7
+palFlag 0
+romFilename Super Mario Bros.
+romChecksum base64:AAECAwQFBgcICQoLDA0ODw==
+guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B
+fourscore 0
+port0 1
+port1 1
+port2 0
+comment author someone
+rustednesState base64:AQIDBA==
+|0|........|........||
+|1|R......A|........||
+|0| L  T  A|.D....B.||
+|2|........|
+";
+
+    fn assert_same(a: &Movie, b: &Movie) {
+        assert_eq!(a.rom_filename, b.rom_filename);
+        assert_eq!(a.rom_md5, b.rom_md5);
+        assert_eq!(a.guid, b.guid);
+        assert_eq!(a.rerecord_count, b.rerecord_count);
+        assert_eq!(a.start_state, b.start_state);
+        assert_eq!(a.frames, b.frames);
+    }
+
+    #[test]
+    fn parses_movie() {
+        let movie = parse(MOVIE).unwrap();
+        assert_eq!(movie.rom_filename, "Super Mario Bros.");
+        assert_eq!(movie.rom_md5, std::array::from_fn(|i| i as u8));
+        assert_eq!(movie.guid, "452DE2C3-EF43-2FA9-77AC-0677FC51543B");
+        assert_eq!(movie.rerecord_count, 7);
+        assert_eq!(movie.start_state, Some(vec![1, 2, 3, 4]));
+        assert_eq!(
+            movie.frames,
+            [
+                MovieFrame::default(),
+                MovieFrame {
+                    commands: COMMAND_RESET,
+                    buttons: [0x81, 0],
+                },
+                MovieFrame {
+                    commands: 0,
+                    buttons: [0x49, 0x42],
+                },
+                MovieFrame {
+                    commands: COMMAND_POWER,
+                    buttons: [0, 0],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_file() {
+        let movie = parse(MOVIE).unwrap();
+        let path = std::env::temp_dir().join(format!("rustednes-movie-{}.fm2", std::process::id()));
+
+        movie.save(&path).unwrap();
+        let saved = Movie::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_same(&saved.unwrap(), &movie);
+    }
+
+    #[test]
+    fn new_movie_round_trips_without_state() {
+        let mut movie = Movie::new("Game.nes".to_string(), [0xab; 16], None);
+        movie.frames.push(MovieFrame {
+            commands: 0,
+            buttons: [0xff, 0x10],
+        });
+        let path = std::env::temp_dir().join(format!("rustednes-new-{}.fm2", std::process::id()));
+
+        movie.save(&path).unwrap();
+        let saved = Movie::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_same(&saved.unwrap(), &movie);
+    }
+
+    #[test]
+    fn formats_buttons() {
+        assert_eq!(format_buttons(0), "........");
+        assert_eq!(format_buttons(0x82), "R.....B.");
+        assert_eq!(format_buttons(0xff), "RLDUTSBA");
+        assert_eq!(parse_buttons("R.....B."), Some(0x82));
+        assert_eq!(parse_buttons("RLDU"), None);
+    }
+
+    #[test]
+    fn rejects_unsupported_movies() {
+        for (text, line) in [
+            ("version 3\npalFlag 1\n", 2),
+            ("fourscore 1\n", 1),
+            ("savestate base64:AA==\n", 1),
+            ("romChecksum base64:AAAA\n", 1),
+            ("|0|........||\n|x|........||\n", 2),
+            ("|0|RLDU|........||\n", 1),
+        ] {
+            match parse(text) {
+                Err(MovieError::Invalid { line: got, .. }) => assert_eq!(got, line, "{text}"),
+                other => panic!("{text}: {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! A software NTSC filter in the style of blargg's nes_ntsc.
+//!
+//! Each line of palette indices is turned into the composite signal the PPU would put out, eight
+//! samples per pixel with twelve samples to a colour subcarrier cycle, and then decoded the way a
+//! TV does. Decoding blurs colour across neighbouring pixels and lets sharp changes in brightness
+//! leak into the colour, which is what gives the fringes and dot crawl some games were drawn for.
+//! Everything runs on the CPU.
+
+use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Width of the filtered image, which keeps the same height as the NES screen.
+pub const NTSC_WIDTH: usize = 602;
+
+/// Signal samples per subcarrier cycle.
+const PHASES: usize = 12;
+const SAMPLES_PER_PIXEL: usize = 8;
+const LINE_SAMPLES: usize = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+/// Palette indices along with the three emphasis bits.
+const COLORS: usize = 512;
+
+/// How far the subcarrier phase moves between the start of one line and the next.
+const LINE_PHASE_STEP: usize = 4;
+
+/// Lines up the decoder with the colour burst so that colour 1 comes out blue.
+const HUE: f32 = 4.0;
+
+/// Boosts the colour decoded from the signal, which comes out washed out otherwise.
+const SATURATION: f32 = 1.4;
+
+/// The kind of video connection to emulate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum NtscPreset {
+    /// Brightness and colour share one signal, with all the fringing that brings.
+    Composite,
+    /// Brightness and colour are kept apart, so only the colour is blurred.
+    SVideo,
+    /// Sharp pixels in the colours the signal decodes to.
+    Rgb,
+    /// A composite signal on a black and white TV.
+    Monochrome,
+}
+
+impl NtscPreset {
+    pub const ALL: [NtscPreset; 4] = [
+        NtscPreset::Composite,
+        NtscPreset::SVideo,
+        NtscPreset::Rgb,
+        NtscPreset::Monochrome,
+    ];
+
+    fn setup(self) -> Setup {
+        match self {
+            NtscPreset::Composite => Setup {
+                separate: false,
+                luma_window: PHASES,
+                chroma_window: PHASES * 2,
+                saturation: SATURATION,
+            },
+            NtscPreset::SVideo => Setup {
+                separate: true,
+                luma_window: 4,
+                chroma_window: PHASES * 2,
+                saturation: SATURATION,
+            },
+            NtscPreset::Rgb => Setup {
+                separate: true,
+                luma_window: 1,
+                chroma_window: 0,
+                saturation: SATURATION,
+            },
+            NtscPreset::Monochrome => Setup {
+                separate: false,
+                luma_window: PHASES,
+                chroma_window: PHASES * 2,
+                saturation: 0.0,
+            },
+        }
+    }
+}
+
+struct Setup {
+    /// Whether brightness and colour are decoded from separate signals.
+    separate: bool,
+    /// How many samples brightness is averaged over.
+    luma_window: usize,
+    /// How many samples colour is averaged over. Zero takes each pixel's own colour.
+    chroma_window: usize,
+    saturation: f32,
+}
+
+/// The signal for one palette index and what it decodes to when the colour fills the line.
+struct ColorSignal {
+    levels: [f32; PHASES],
+    y: f32,
+    i: f32,
+    q: f32,
+}
+
+/// Filters frames through an emulated NTSC signal. The palette setting has no effect here, as
+/// the colours come from decoding the signal.
+pub struct NtscFilter {
+    preset: NtscPreset,
+    setup: Setup,
+    colors: Vec<ColorSignal>,
+    carrier: [(f32, f32); PHASES],
+    /// The subcarrier phase at the start of the frame, which alternates between frames like on
+    /// the real console.
+    burst_phase: usize,
+    /// Running totals of the decoded signals along the current line.
+    luma: Vec<f32>,
+    i: Vec<f32>,
+    q: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(preset: NtscPreset) -> NtscFilter {
+        let colors = (0..COLORS)
+            .map(|index| {
+                let levels = composite_levels(index);
+                let (y, i, q) = demodulate(&levels);
+                ColorSignal { levels, y, i, q }
+            })
+            .collect();
+
+        NtscFilter {
+            preset,
+            setup: preset.setup(),
+            colors,
+            carrier: std::array::from_fn(carrier),
+            burst_phase: 0,
+            luma: vec![0.0; LINE_SAMPLES + 1],
+            i: vec![0.0; LINE_SAMPLES + 1],
+            q: vec![0.0; LINE_SAMPLES + 1],
+        }
+    }
+
+    pub fn preset(&self) -> NtscPreset {
+        self.preset
+    }
+
+    /// Filters a frame of palette indices into `pixels` as RGBA, `NTSC_WIDTH` pixels wide, with
+    /// the PPU's emphasis bits for red, green and blue in the lowest three bits of `emphasis`.
+    pub fn render(&mut self, frame_buffer: &[u8], emphasis: u8, pixels: &mut [u8]) {
+        let emphasis = usize::from(emphasis & 0x07) << 6;
+        let lines = frame_buffer
+            .chunks_exact(SCREEN_WIDTH)
+            .zip(pixels.chunks_exact_mut(NTSC_WIDTH * 4))
+            .take(SCREEN_HEIGHT);
+        for (line_number, (line, out)) in lines.enumerate() {
+            let phase = (self.burst_phase + line_number * LINE_PHASE_STEP) % PHASES;
+            self.render_line(line, emphasis, phase, out);
+        }
+
+        // Every other frame is a pixel shorter, which moves the phase the other way.
+        self.burst_phase = (self.burst_phase + LINE_PHASE_STEP) % (LINE_PHASE_STEP * 2);
+    }
+
+    fn render_line(&mut self, line: &[u8], emphasis: usize, phase: usize, out: &mut [u8]) {
+        let mut sample_phase = phase;
+        for (pixel, &index) in line.iter().enumerate() {
+            let color = &self.colors[emphasis | usize::from(index & 0x3f)];
+            for sample in pixel * SAMPLES_PER_PIXEL..(pixel + 1) * SAMPLES_PER_PIXEL {
+                let level = color.levels[sample_phase];
+                let (luma, chroma) = if self.setup.separate {
+                    (color.y, level - color.y)
+                } else {
+                    (level, level)
+                };
+                let (cos, sin) = self.carrier[sample_phase];
+
+                self.luma[sample + 1] = self.luma[sample] + luma;
+                self.i[sample + 1] = self.i[sample] + chroma * cos;
+                self.q[sample + 1] = self.q[sample] + chroma * sin;
+
+                sample_phase += 1;
+                if sample_phase == PHASES {
+                    sample_phase = 0;
+                }
+            }
+        }
+
+        for (x, pixel) in out.chunks_exact_mut(4).enumerate() {
+            let center = (x * 2 + 1) * LINE_SAMPLES / (NTSC_WIDTH * 2);
+            let y = window_average(&self.luma, center, self.setup.luma_window);
+            let (i, q) = if self.setup.chroma_window == 0 {
+                let index = line[center / SAMPLES_PER_PIXEL];
+                let color = &self.colors[emphasis | usize::from(index & 0x3f)];
+                (color.i, color.q)
+            } else {
+                (
+                    window_average(&self.i, center, self.setup.chroma_window),
+                    window_average(&self.q, center, self.setup.chroma_window),
+                )
+            };
+
+            let [r, g, b] = yiq_to_rgb(y, i * self.setup.saturation, q * self.setup.saturation);
+            pixel.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+}
+
+/// The average of a signal over `width` samples around `center`, from its running totals.
+fn window_average(totals: &[f32], center: usize, width: usize) -> f32 {
+    let end = (center.saturating_sub(width / 2) + width).min(LINE_SAMPLES);
+    let start = end.saturating_sub(width);
+    (totals[end] - totals[start]) / (end - start) as f32
+}
+
+/// The signal level for a palette index at each subcarrier phase, scaled so that black is 0 and
+/// white is 1. Bits 6 to 8 of the index are the emphasis bits for red, green and blue.
+fn composite_levels(index: usize) -> [f32; PHASES] {
+    // Signal levels for each row of the palette, relative to sync.
+    const LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+    const HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+    const BLACK: f32 = 0.518;
+    const WHITE: f32 = 1.962;
+    const ATTENUATION: f32 = 0.746;
+    // The colours whose phases each emphasis bit dims: red, green and blue.
+    const EMPHASIS_COLORS: [usize; 3] = [0x0c, 0x04, 0x08];
+
+    let in_color_phase = |color: usize, phase: usize| (color + phase) % PHASES < PHASES / 2;
+
+    let color = index & 0x0f;
+    let row = if color >= 0x0e {
+        1
+    } else {
+        (index >> 4) & 0x03
+    };
+    let emphasis = index >> 6;
+
+    let mut low = LOW[row];
+    let mut high = HIGH[row];
+    if color == 0x00 {
+        low = high;
+    } else if color >= 0x0d {
+        high = low;
+    }
+
+    let mut levels = [0.0; PHASES];
+    for (phase, level) in levels.iter_mut().enumerate() {
+        let mut signal = if in_color_phase(color, phase) {
+            high
+        } else {
+            low
+        };
+        let attenuated = EMPHASIS_COLORS
+            .iter()
+            .enumerate()
+            .any(|(bit, &tint)| emphasis & (1 << bit) != 0 && in_color_phase(tint, phase));
+        if attenuated && color < 0x0e {
+            signal *= ATTENUATION;
+        }
+        *level = (signal - BLACK) / (WHITE - BLACK);
+    }
+    levels
+}
+
+/// Decodes one subcarrier cycle of a signal into YIQ.
+fn demodulate(levels: &[f32; PHASES]) -> (f32, f32, f32) {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for (phase, level) in levels.iter().enumerate() {
+        let (cos, sin) = carrier(phase);
+        y += level;
+        i += level * cos;
+        q += level * sin;
+    }
+
+    let cycle = PHASES as f32;
+    (y / cycle, i / cycle, q / cycle)
+}
+
+fn yiq_to_rgb(y: f32, i: f32, q: f32) -> [u8; 3] {
+    [
+        to_srgb(y + 0.956 * i + 0.621 * q),
+        to_srgb(y - 0.272 * i - 0.647 * q),
+        to_srgb(y - 1.106 * i + 1.703 * q),
+    ]
+}
+
+fn carrier(phase: usize) -> (f32, f32) {
+    let angle = PI * (phase as f32 + HUE) / 6.0;
+    (angle.cos(), angle.sin())
+}
+
+fn to_srgb(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
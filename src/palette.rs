@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Colour palettes that turn the PPU's palette indices into RGB.
+//!
+//! A palette has 64 colours for each of the eight combinations of the PPU's colour emphasis
+//! bits. Palettes that only give the 64 base colours have the emphasis variants worked out by
+//! dimming the channels that aren't emphasized.
+
+use rustednes_core::sink::XRGB8888_PALETTE;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BASE_COLORS: usize = 64;
+const EMPHASIS_VARIANTS: usize = 8;
+const PALETTE_LEN: usize = BASE_COLORS * EMPHASIS_VARIANTS;
+
+/// How much emphasis dims the other channels of a base colour.
+const EMPHASIS_ATTENUATION: f32 = 0.816_328;
+
+/// The palette FCEUX uses by default.
+#[rustfmt::skip]
+const FCEUX: [[u8; 3]; BASE_COLORS] = [
+    [0x74, 0x74, 0x74], [0x24, 0x18, 0x8c], [0x00, 0x00, 0xa8], [0x44, 0x00, 0x9c],
+    [0x8c, 0x00, 0x74], [0xa8, 0x00, 0x10], [0xa4, 0x00, 0x00], [0x7c, 0x08, 0x00],
+    [0x40, 0x2c, 0x00], [0x00, 0x44, 0x00], [0x00, 0x50, 0x00], [0x00, 0x3c, 0x14],
+    [0x18, 0x3c, 0x5c], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xbc, 0xbc, 0xbc], [0x00, 0x70, 0xec], [0x20, 0x38, 0xec], [0x80, 0x00, 0xf0],
+    [0xbc, 0x00, 0xbc], [0xe4, 0x00, 0x58], [0xd8, 0x28, 0x00], [0xc8, 0x4c, 0x0c],
+    [0x88, 0x70, 0x00], [0x00, 0x94, 0x00], [0x00, 0xa8, 0x00], [0x00, 0x90, 0x38],
+    [0x00, 0x80, 0x88], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xfc, 0xfc, 0xfc], [0x3c, 0xbc, 0xfc], [0x5c, 0x94, 0xfc], [0xcc, 0x88, 0xfc],
+    [0xf4, 0x78, 0xfc], [0xfc, 0x74, 0xb4], [0xfc, 0x74, 0x60], [0xfc, 0x98, 0x38],
+    [0xf0, 0xbc, 0x3c], [0x80, 0xd0, 0x10], [0x4c, 0xdc, 0x48], [0x58, 0xf8, 0x98],
+    [0x00, 0xe8, 0xd8], [0x78, 0x78, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xfc, 0xfc, 0xfc], [0xa8, 0xe4, 0xfc], [0xc4, 0xd4, 0xfc], [0xd4, 0xc8, 0xfc],
+    [0xfc, 0xc4, 0xfc], [0xfc, 0xc4, 0xd8], [0xfc, 0xbc, 0xb0], [0xfc, 0xd8, 0xa8],
+    [0xfc, 0xe4, 0xa0], [0xe0, 0xfc, 0xa0], [0xa8, 0xf0, 0xbc], [0xb0, 0xfc, 0xcc],
+    [0x9c, 0xfc, 0xf0], [0xc4, 0xc4, 0xc4], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+/// The palette Nestopia decodes from the NTSC signal with its default YUV settings.
+#[rustfmt::skip]
+const NESTOPIA: [[u8; 3]; BASE_COLORS] = [
+    [0x66, 0x66, 0x66], [0x00, 0x2a, 0x88], [0x14, 0x12, 0xa7], [0x3b, 0x00, 0xa4],
+    [0x5c, 0x00, 0x7e], [0x6e, 0x00, 0x40], [0x6c, 0x07, 0x00], [0x56, 0x1d, 0x00],
+    [0x33, 0x35, 0x00], [0x0c, 0x48, 0x00], [0x00, 0x52, 0x00], [0x00, 0x4f, 0x08],
+    [0x00, 0x40, 0x4d], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xad, 0xad, 0xad], [0x15, 0x5f, 0xd9], [0x42, 0x40, 0xff], [0x75, 0x27, 0xfe],
+    [0xa0, 0x1a, 0xcc], [0xb7, 0x1e, 0x7b], [0xb5, 0x31, 0x20], [0x99, 0x4e, 0x00],
+    [0x6b, 0x6d, 0x00], [0x38, 0x87, 0x00], [0x0d, 0x93, 0x00], [0x00, 0x8f, 0x32],
+    [0x00, 0x7c, 0x8d], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xff, 0xff, 0xff], [0x64, 0xb0, 0xff], [0x92, 0x90, 0xff], [0xc6, 0x76, 0xff],
+    [0xf2, 0x6a, 0xff], [0xff, 0x6e, 0xcc], [0xff, 0x81, 0x70], [0xea, 0x9e, 0x22],
+    [0xbc, 0xbe, 0x00], [0x88, 0xd8, 0x00], [0x5c, 0xe4, 0x30], [0x45, 0xe0, 0x82],
+    [0x48, 0xcd, 0xde], [0x4f, 0x4f, 0x4f], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xff, 0xff, 0xff], [0xc0, 0xdf, 0xff], [0xd3, 0xd2, 0xff], [0xe8, 0xc8, 0xff],
+    [0xfa, 0xc2, 0xff], [0xff, 0xc4, 0xea], [0xff, 0xcc, 0xc5], [0xf7, 0xd8, 0xa5],
+    [0xe4, 0xe5, 0x94], [0xcf, 0xef, 0x96], [0xbd, 0xf4, 0xab], [0xb3, 0xf3, 0xcc],
+    [0xb5, 0xeb, 0xf2], [0xb8, 0xb8, 0xb8], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+/// FirebrandX's Smooth palette, measured from the composite output of a real console.
+#[rustfmt::skip]
+const SMOOTH: [[u8; 3]; BASE_COLORS] = [
+    [0x6a, 0x6d, 0x6a], [0x00, 0x13, 0x80], [0x1e, 0x00, 0x8a], [0x39, 0x00, 0x7a],
+    [0x55, 0x00, 0x56], [0x5a, 0x00, 0x18], [0x4f, 0x10, 0x00], [0x3d, 0x1c, 0x00],
+    [0x25, 0x32, 0x00], [0x00, 0x3d, 0x00], [0x00, 0x40, 0x00], [0x00, 0x39, 0x24],
+    [0x00, 0x2e, 0x55], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xb9, 0xbc, 0xb9], [0x18, 0x50, 0xc7], [0x4b, 0x30, 0xe3], [0x73, 0x22, 0xd6],
+    [0x95, 0x1f, 0xa9], [0x9d, 0x28, 0x5c], [0x98, 0x37, 0x00], [0x7f, 0x4c, 0x00],
+    [0x5e, 0x64, 0x00], [0x22, 0x77, 0x00], [0x02, 0x7e, 0x02], [0x00, 0x76, 0x45],
+    [0x00, 0x6e, 0x8a], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xff, 0xff, 0xff], [0x68, 0xa6, 0xff], [0x8c, 0x9c, 0xff], [0xb5, 0x86, 0xff],
+    [0xd9, 0x75, 0xfd], [0xe3, 0x77, 0xb9], [0xe5, 0x8d, 0x68], [0xd4, 0x9d, 0x29],
+    [0xb3, 0xaf, 0x0c], [0x7b, 0xc2, 0x11], [0x55, 0xca, 0x47], [0x46, 0xcb, 0x81],
+    [0x47, 0xc1, 0xc5], [0x4a, 0x4d, 0x4a], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xff, 0xff, 0xff], [0xcc, 0xea, 0xff], [0xdd, 0xde, 0xff], [0xec, 0xda, 0xff],
+    [0xf8, 0xd7, 0xfe], [0xfc, 0xd6, 0xf5], [0xfd, 0xdb, 0xcf], [0xf9, 0xe7, 0xb5],
+    [0xf1, 0xf0, 0xaa], [0xda, 0xfa, 0xa9], [0xc9, 0xff, 0xbc], [0xc3, 0xfb, 0xd7],
+    [0xc4, 0xf6, 0xf6], [0xbe, 0xc1, 0xbe], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+/// Which palette to draw with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub enum PaletteChoice {
+    /// The palette built into the emulator core.
+    #[default]
+    Default,
+    Fceux,
+    Nestopia,
+    Smooth,
+    /// Loaded from the `.pal` file in the config.
+    Custom,
+}
+
+impl PaletteChoice {
+    pub const BUILT_IN: [PaletteChoice; 4] = [
+        PaletteChoice::Default,
+        PaletteChoice::Fceux,
+        PaletteChoice::Nestopia,
+        PaletteChoice::Smooth,
+    ];
+}
+
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let colors = XRGB8888_PALETTE
+            .iter()
+            .map(|pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8])
+            .collect();
+        Palette::from_colors(colors)
+    }
+}
+
+impl Palette {
+    /// A built-in palette. Asking for the custom palette gives the default one.
+    pub fn built_in(choice: PaletteChoice) -> Palette {
+        match choice {
+            PaletteChoice::Default | PaletteChoice::Custom => Palette::default(),
+            PaletteChoice::Fceux => Palette::from_colors(FCEUX.to_vec()),
+            PaletteChoice::Nestopia => Palette::from_colors(NESTOPIA.to_vec()),
+            PaletteChoice::Smooth => Palette::from_colors(SMOOTH.to_vec()),
+        }
+    }
+
+    /// Loads a `.pal` file holding either the 64 base colours or all 512 emphasis variants as
+    /// RGB triples.
+    pub fn load(path: &Path) -> Result<Palette, PaletteError> {
+        let data = fs::read(path)?;
+        if data.len() != BASE_COLORS * 3 && data.len() != PALETTE_LEN * 3 {
+            return Err(PaletteError::BadSize(data.len()));
+        }
+
+        let colors = data
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect();
+        Ok(Palette::from_colors(colors))
+    }
+
+    /// The colour of a palette index from the core's frame buffer, with the PPU's emphasis bits
+    /// for red, green and blue in the lowest three bits of `emphasis`.
+    pub fn rgb(&self, index: u8, emphasis: u8) -> [u8; 3] {
+        let emphasis = usize::from(emphasis) % EMPHASIS_VARIANTS;
+        self.colors[emphasis * BASE_COLORS + usize::from(index) % BASE_COLORS]
+    }
+
+    fn from_colors(mut colors: Vec<[u8; 3]>) -> Palette {
+        colors.truncate(PALETTE_LEN);
+        if colors.len() < PALETTE_LEN {
+            colors.resize(BASE_COLORS, [0; 3]);
+            colors = (0..EMPHASIS_VARIANTS)
+                .flat_map(|emphasis| colors.iter().map(move |rgb| emphasize(*rgb, emphasis)))
+                .collect();
+        }
+        Palette { colors }
+    }
+}
+
+/// Dims the channels of a base colour that the emphasis bits don't emphasize.
+fn emphasize(rgb: [u8; 3], emphasis: usize) -> [u8; 3] {
+    let mut factors = [1.0f32; 3];
+    for bit in 0..3 {
+        if emphasis & (1 << bit) != 0 {
+            for (channel, factor) in factors.iter_mut().enumerate() {
+                if channel != bit {
+                    *factor *= EMPHASIS_ATTENUATION;
+                }
+            }
+        }
+    }
+
+    [0, 1, 2].map(|channel| (f32::from(rgb[channel]) * factors[channel]).round() as u8)
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    BadSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(err) => write!(f, "{err}"),
+            PaletteError::BadSize(len) => write!(
+                f,
+                "palette files should be {} or {} bytes, not {len}",
+                BASE_COLORS * 3,
+                PALETTE_LEN * 3
+            ),
+        }
+    }
+}
+
+impl Error for PaletteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PaletteError::Io(err) => Some(err),
+            PaletteError::BadSize(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(err: io::Error) -> Self {
+        PaletteError::Io(err)
+    }
+}
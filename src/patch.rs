@@ -0,0 +1,530 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const IPS_MAGIC: &[u8; 5] = b"PATCH";
+const IPS_EOF: &[u8; 3] = b"EOF";
+const UPS_MAGIC: &[u8; 4] = b"UPS1";
+const BPS_MAGIC: &[u8; 4] = b"BPS1";
+
+/// UPS and BPS patches end with the CRC32 of the source, the target and the patch itself.
+const CHECKSUM_FOOTER_LEN: usize = 12;
+
+/// The largest ROM a patch may produce. The biggest NES games are a few megabytes, so anything
+/// past this is a corrupt patch rather than a real game.
+const MAX_TARGET_LEN: usize = 32 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    pub const ALL: [PatchFormat; 3] = [PatchFormat::Ips, PatchFormat::Ups, PatchFormat::Bps];
+
+    pub fn of(path: &Path) -> Option<PatchFormat> {
+        let ext = path.extension()?;
+        PatchFormat::ALL
+            .into_iter()
+            .find(|format| ext.eq_ignore_ascii_case(format.extension()))
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PatchFormat::Ips => "ips",
+            PatchFormat::Ups => "ups",
+            PatchFormat::Bps => "bps",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchError {
+    UnknownFormat,
+    BadMagic,
+    Truncated,
+    InvalidAction,
+    /// The patch was made for a different ROM.
+    SourceMismatch,
+    /// Applying the patch didn't produce the ROM it was made to produce.
+    TargetMismatch,
+    /// The patch file itself is corrupt.
+    PatchChecksumMismatch,
+    /// The patched ROM would be bigger than any NES game.
+    TargetTooLarge,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "unknown patch format"),
+            PatchError::BadMagic => write!(f, "patch file doesn't match its format"),
+            PatchError::Truncated => write!(f, "patch file is truncated"),
+            PatchError::InvalidAction => write!(f, "patch file contains an invalid action"),
+            PatchError::SourceMismatch => {
+                write!(
+                    f,
+                    "the patch was made for a different ROM (checksum mismatch)"
+                )
+            }
+            PatchError::TargetMismatch => {
+                write!(f, "the patched ROM doesn't match the patch's checksum")
+            }
+            PatchError::PatchChecksumMismatch => write!(f, "patch file is corrupt"),
+            PatchError::TargetTooLarge => write!(f, "the patched ROM would be too large"),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+pub fn is_patch_file(path: &Path) -> bool {
+    PatchFormat::of(path).is_some()
+}
+
+/// Finds a patch with the same name as the ROM next to it, e.g. `game.ips` for `game.nes`.
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PatchFormat::ALL
+        .into_iter()
+        .map(|format| rom_path.with_extension(format.extension()))
+        .find(|path| path.is_file())
+}
+
+/// Applies the patch at `path` to `rom`, choosing the format from the file extension. If the
+/// patch can't be applied `rom` is left as it was.
+pub fn apply(rom: &mut Vec<u8>, path: &Path, patch: &[u8]) -> Result<(), PatchError> {
+    match PatchFormat::of(path).ok_or(PatchError::UnknownFormat)? {
+        PatchFormat::Ips => apply_ips(rom, patch),
+        PatchFormat::Ups => apply_ups(rom, patch),
+        PatchFormat::Bps => apply_bps(rom, patch),
+    }
+}
+
+/// Applies an IPS patch to `rom`, growing it if the patch writes past the end.
+fn apply_ips(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let mut reader = PatchReader::new(patch);
+    if reader.take(IPS_MAGIC.len())? != IPS_MAGIC {
+        return Err(PatchError::BadMagic);
+    }
+
+    let mut target = rom.clone();
+
+    loop {
+        let offset = reader.take(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = be_u24(offset);
+
+        let data_len = be_u16(reader.take(2)?);
+        if data_len == 0 {
+            // Run-length encoded record.
+            let run_len = be_u16(reader.take(2)?);
+            let value = reader.byte()?;
+            write_at(&mut target, offset, &vec![value; run_len])?;
+        } else {
+            write_at(&mut target, offset, reader.take(data_len)?)?;
+        }
+    }
+
+    // Some patches end with the size the patched file should be truncated to.
+    if let Ok(len) = reader.take(3) {
+        target.truncate(be_u24(len));
+    }
+
+    *rom = target;
+    Ok(())
+}
+
+/// Applies a UPS patch, which XORs runs of bytes into the ROM.
+fn apply_ups(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let (body, checksums) = split_checksums(patch, UPS_MAGIC)?;
+    if crc32fast::hash(rom) != checksums.source {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    let mut reader = PatchReader::new(body);
+    let source_len = reader.varint()?;
+    let target_len = reader.varint()?;
+    if rom.len() != source_len {
+        return Err(PatchError::SourceMismatch);
+    }
+    check_target_len(target_len)?;
+
+    let mut target = rom.clone();
+    target.resize(target_len, 0);
+
+    let mut offset = 0usize;
+    while !reader.is_empty() {
+        offset = checked_add(offset, reader.varint()?)?;
+        loop {
+            let value = reader.byte()?;
+            if value == 0 {
+                break;
+            }
+            if let Some(byte) = target.get_mut(offset) {
+                *byte ^= value;
+            }
+            offset = checked_add(offset, 1)?;
+        }
+        // The terminating zero also counts as a byte of the hunk.
+        offset = checked_add(offset, 1)?;
+    }
+
+    if crc32fast::hash(&target) != checksums.target {
+        return Err(PatchError::TargetMismatch);
+    }
+
+    *rom = target;
+    Ok(())
+}
+
+/// Applies a BPS patch, which builds a new ROM from copies of the source, the patch and what
+/// has been written so far.
+fn apply_bps(rom: &mut Vec<u8>, patch: &[u8]) -> Result<(), PatchError> {
+    let (body, checksums) = split_checksums(patch, BPS_MAGIC)?;
+    if crc32fast::hash(rom) != checksums.source {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    let mut reader = PatchReader::new(body);
+    let source_len = reader.varint()?;
+    let target_len = reader.varint()?;
+    let metadata_len = reader.varint()?;
+    reader.take(metadata_len)?;
+    if rom.len() != source_len {
+        return Err(PatchError::SourceMismatch);
+    }
+    check_target_len(target_len)?;
+
+    let source = rom.as_slice();
+    let mut target = Vec::with_capacity(target_len);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+
+    while !reader.is_empty() {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        if len > target_len - target.len() {
+            return Err(PatchError::InvalidAction);
+        }
+
+        match action & 3 {
+            // Source read: copy from the same position in the source.
+            0 => {
+                let start = target.len();
+                let bytes = source
+                    .get(start..checked_add(start, len)?)
+                    .ok_or(PatchError::InvalidAction)?;
+                target.extend_from_slice(bytes);
+            }
+            // Target read: copy from the patch.
+            1 => target.extend_from_slice(reader.take(len)?),
+            // Source copy: copy from a relative position in the source.
+            2 => {
+                source_offset = relative_offset(source_offset, reader.varint()?)?;
+                let end = checked_add(source_offset, len)?;
+                let bytes = source
+                    .get(source_offset..end)
+                    .ok_or(PatchError::InvalidAction)?;
+                target.extend_from_slice(bytes);
+                source_offset = end;
+            }
+            // Target copy: copy from earlier in the output. The ranges can overlap, so this has
+            // to go a byte at a time.
+            _ => {
+                target_offset = relative_offset(target_offset, reader.varint()?)?;
+                for _ in 0..len {
+                    let byte = *target.get(target_offset).ok_or(PatchError::InvalidAction)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_len || crc32fast::hash(&target) != checksums.target {
+        return Err(PatchError::TargetMismatch);
+    }
+
+    *rom = target;
+    Ok(())
+}
+
+struct Checksums {
+    source: u32,
+    target: u32,
+}
+
+/// Checks the magic and the patch checksum of a UPS or BPS patch, returning the actions between
+/// the magic and the checksum footer.
+fn split_checksums<'a>(patch: &'a [u8], magic: &[u8]) -> Result<(&'a [u8], Checksums), PatchError> {
+    if patch.len() < magic.len() + CHECKSUM_FOOTER_LEN {
+        return Err(PatchError::Truncated);
+    }
+    if &patch[..magic.len()] != magic {
+        return Err(PatchError::BadMagic);
+    }
+
+    let footer_start = patch.len() - CHECKSUM_FOOTER_LEN;
+    let footer = &patch[footer_start..];
+    let patch_checksum = le_u32(&footer[8..12]);
+    if crc32fast::hash(&patch[..patch.len() - 4]) != patch_checksum {
+        return Err(PatchError::PatchChecksumMismatch);
+    }
+
+    let checksums = Checksums {
+        source: le_u32(&footer[0..4]),
+        target: le_u32(&footer[4..8]),
+    };
+    Ok((&patch[magic.len()..footer_start], checksums))
+}
+
+/// Moves an offset by a BPS relative offset, whose lowest bit is the sign.
+fn relative_offset(offset: usize, encoded: usize) -> Result<usize, PatchError> {
+    let delta = encoded >> 1;
+    if encoded & 1 != 0 {
+        offset.checked_sub(delta).ok_or(PatchError::InvalidAction)
+    } else {
+        checked_add(offset, delta)
+    }
+}
+
+fn checked_add(offset: usize, len: usize) -> Result<usize, PatchError> {
+    offset.checked_add(len).ok_or(PatchError::InvalidAction)
+}
+
+fn check_target_len(len: usize) -> Result<(), PatchError> {
+    if len > MAX_TARGET_LEN {
+        return Err(PatchError::TargetTooLarge);
+    }
+    Ok(())
+}
+
+fn write_at(rom: &mut Vec<u8>, offset: usize, data: &[u8]) -> Result<(), PatchError> {
+    let end = checked_add(offset, data.len())?;
+    check_target_len(end)?;
+    if rom.len() < end {
+        rom.resize(end, 0);
+    }
+    rom[offset..end].copy_from_slice(data);
+    Ok(())
+}
+
+fn be_u16(bytes: &[u8]) -> usize {
+    usize::from(bytes[0]) << 8 | usize::from(bytes[1])
+}
+
+fn be_u24(bytes: &[u8]) -> usize {
+    usize::from(bytes[0]) << 16 | usize::from(bytes[1]) << 8 | usize::from(bytes[2])
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(len).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Reads a variable-length number as used by UPS and BPS, where every byte but the last
+    /// has its high bit clear.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.byte()?;
+            value = usize::from(byte & 0x7f)
+                .checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or(PatchError::InvalidAction)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::InvalidAction)?;
+            value = value.checked_add(shift).ok_or(PatchError::InvalidAction)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    /// Ends a UPS or BPS patch with the checksums of the source, the target and the patch.
+    fn finish(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    fn ups_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = UPS_MAGIC.to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        // Skip a byte, XOR in one byte, then skip to the end and XOR in the new byte.
+        patch.extend(varint(1));
+        patch.extend([source[1] ^ target[1], 0]);
+        patch.extend(varint(1));
+        patch.extend([target[4], 0]);
+        finish(patch, source, target)
+    }
+
+    #[test]
+    fn ips_applies_records_and_truncates() {
+        let mut rom = vec![0; 8];
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend([0, 0, 1, 0, 2, 0xaa, 0xbb]);
+        // Run-length encoded record.
+        patch.extend([0, 0, 4, 0, 0, 0, 3, 0xcc]);
+        // Writes past the end of the ROM, growing it.
+        patch.extend([0, 0, 8, 0, 2, 0xee, 0xff]);
+        patch.extend(IPS_EOF);
+        patch.extend([0, 0, 9]);
+
+        apply_ips(&mut rom, &patch).unwrap();
+        assert_eq!(rom, [0, 0xaa, 0xbb, 0, 0xcc, 0xcc, 0xcc, 0, 0xee]);
+    }
+
+    #[test]
+    fn ips_rejects_bad_magic() {
+        let mut rom = vec![0; 8];
+        assert_eq!(apply_ips(&mut rom, b"PATCX"), Err(PatchError::BadMagic));
+    }
+
+    #[test]
+    fn ups_produces_target() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 9, 3, 4, 5];
+        let mut rom = source.to_vec();
+
+        apply_ups(&mut rom, &ups_patch(&source, &target)).unwrap();
+        assert_eq!(rom, target);
+    }
+
+    #[test]
+    fn ups_rejects_checksum_mismatches() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 9, 3, 4, 5];
+        let patch = ups_patch(&source, &target);
+
+        let mut other_rom = vec![1, 2, 3, 5];
+        assert_eq!(
+            apply_ups(&mut other_rom, &patch),
+            Err(PatchError::SourceMismatch)
+        );
+        assert_eq!(other_rom, [1, 2, 3, 5]);
+
+        // A patch claiming to produce something other than what its hunks produce.
+        let mut body = patch[..patch.len() - CHECKSUM_FOOTER_LEN].to_vec();
+        body.extend_from_slice(&crc32fast::hash(&source).to_le_bytes());
+        body.extend_from_slice(&crc32fast::hash(&[0; 5]).to_le_bytes());
+        body.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        let mut rom = source.to_vec();
+        assert_eq!(apply_ups(&mut rom, &body), Err(PatchError::TargetMismatch));
+        assert_eq!(rom, source);
+    }
+
+    fn bps_patch(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let action = |len: usize, kind: usize| varint((len - 1) << 2 | kind);
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // Source read of "AB".
+        patch.extend(action(2, 0));
+        // Target read of "xyz".
+        patch.extend(action(3, 1));
+        patch.extend(b"xyz");
+        // Source copy of "GH", six bytes on from the start of the source.
+        patch.extend(action(2, 2));
+        patch.extend(varint(6 << 1));
+        // Target copy that overlaps the bytes it writes, starting from "GH".
+        patch.extend(action(3, 3));
+        patch.extend(varint(5 << 1));
+        finish(patch, source, target)
+    }
+
+    #[test]
+    fn bps_applies_all_actions() {
+        let source = b"ABCDEFGH";
+        let target = b"ABxyzGHGHG";
+        let mut rom = source.to_vec();
+
+        apply_bps(&mut rom, &bps_patch(source, target)).unwrap();
+        assert_eq!(rom, target);
+    }
+
+    #[test]
+    fn bps_rejects_corrupt_patch() {
+        let source = b"ABCDEFGH";
+        let mut patch = bps_patch(source, b"ABxyzGHGHG");
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+
+        let mut rom = source.to_vec();
+        assert_eq!(
+            apply_bps(&mut rom, &patch),
+            Err(PatchError::PatchChecksumMismatch)
+        );
+        assert_eq!(rom, source);
+    }
+
+    #[test]
+    fn oversized_targets_are_rejected() {
+        let source = [0u8; 4];
+
+        let mut ups = UPS_MAGIC.to_vec();
+        ups.extend(varint(source.len()));
+        ups.extend(varint(MAX_TARGET_LEN + 1));
+        let ups = finish(ups, &source, &[]);
+        let mut rom = source.to_vec();
+        assert_eq!(apply_ups(&mut rom, &ups), Err(PatchError::TargetTooLarge));
+
+        let mut bps = BPS_MAGIC.to_vec();
+        bps.extend(varint(source.len()));
+        bps.extend(varint(usize::MAX >> 8));
+        bps.extend(varint(0));
+        let bps = finish(bps, &source, &[]);
+        assert_eq!(apply_bps(&mut rom, &bps), Err(PatchError::TargetTooLarge));
+        assert_eq!(rom, source);
+    }
+}
@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Records gameplay to a Y4M video and a WAV file with the same name, or just the sound to a WAV
+//! file.
+//!
+//! Both are uncompressed and written as the game runs, so they are easy to convert with tools
+//! like `ffmpeg -i game.y4m -i game.wav game.mp4`. Frames and samples are recorded in emulated
+//! time, so the recording plays back at normal speed whatever speed the game was running at.
+//! Samples are taken straight from the APU before they're resampled for the output device.
+
+use crate::capture;
+use rustednes_core::apu::SAMPLE_RATE as APU_SAMPLE_RATE;
+use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rustednes_core::sink::AudioSink;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The NTSC NES frame rate as a fraction, 60.0988 frames per second.
+const FRAME_RATE: (u32, u32) = (39_375_000, 655_171);
+
+type WavWriter = hound::WavWriter<BufWriter<File>>;
+
+/// The directory recordings are saved to when the config doesn't name one.
+pub fn default_dir() -> PathBuf {
+    dirs::video_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join("RustedNES")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingKind {
+    /// Video with 16-bit audio alongside it.
+    Video,
+    /// Only audio, as 32-bit floats so the APU's output is kept exactly.
+    Audio,
+}
+
+pub struct Recorder {
+    video: Option<BufWriter<File>>,
+    /// The width of the video, set by the first frame since the NTSC filter makes it wider.
+    video_width: Option<usize>,
+    audio: WavWriter,
+    kind: RecordingKind,
+    /// The video, or the audio if only audio is recorded.
+    path: PathBuf,
+    /// The first write error, after which nothing more is recorded.
+    error: Option<Box<dyn Error>>,
+}
+
+impl Recorder {
+    /// Starts recording to new files in `dir` named after the ROM and the current time.
+    pub fn start(
+        dir: &Path,
+        rom_path: &Path,
+        kind: RecordingKind,
+    ) -> Result<Recorder, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        let name = match kind {
+            RecordingKind::Video => capture::capture_name(dir, rom_path, &["y4m", "wav"]),
+            RecordingKind::Audio => capture::capture_name(dir, rom_path, &["wav"]),
+        };
+        let audio_path = dir.join(format!("{name}.wav"));
+
+        let (video, path) = match kind {
+            RecordingKind::Video => {
+                let video_path = dir.join(format!("{name}.y4m"));
+                let video = BufWriter::new(File::create(&video_path)?);
+                (Some(video), video_path)
+            }
+            RecordingKind::Audio => (None, audio_path.clone()),
+        };
+
+        let spec = match kind {
+            RecordingKind::Video => hound::WavSpec {
+                channels: 1,
+                sample_rate: APU_SAMPLE_RATE,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            },
+            RecordingKind::Audio => hound::WavSpec {
+                channels: 1,
+                sample_rate: APU_SAMPLE_RATE,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            },
+        };
+        let audio = hound::WavWriter::create(&audio_path, spec)?;
+
+        tracing::info!("Recording to {}", path.display());
+        Ok(Recorder {
+            video,
+            video_width: None,
+            audio,
+            kind,
+            path,
+            error: None,
+        })
+    }
+
+    pub fn kind(&self) -> RecordingKind {
+        self.kind
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds an RGBA frame to the video, if video is being recorded.
+    pub fn write_frame(&mut self, pixels: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let Some(video) = &mut self.video else {
+            return;
+        };
+
+        let width = pixels.len() / (SCREEN_HEIGHT * 4);
+        match self.video_width {
+            Some(video_width) if video_width != width => {
+                self.fail("the screen size changed while recording".into());
+                return;
+            }
+            Some(_) => {}
+            None => {
+                // 4:4:4 chroma keeps the NES's sharp pixels intact, and the aspect ratio squeezes
+                // a wide NTSC filtered frame back to the shape of the NES screen.
+                let aspect = if width == SCREEN_WIDTH {
+                    (1, 1)
+                } else {
+                    (SCREEN_WIDTH, width)
+                };
+                let result = writeln!(
+                    video,
+                    "YUV4MPEG2 W{width} H{SCREEN_HEIGHT} F{}:{} Ip A{}:{} C444",
+                    FRAME_RATE.0, FRAME_RATE.1, aspect.0, aspect.1
+                );
+                if let Err(err) = result {
+                    self.fail(err.into());
+                    return;
+                }
+                self.video_width = Some(width);
+            }
+        }
+
+        let plane_len = width * SCREEN_HEIGHT;
+        let mut frame = vec![0u8; plane_len * 3];
+        let (y_plane, chroma) = frame.split_at_mut(plane_len);
+        let (u_plane, v_plane) = chroma.split_at_mut(plane_len);
+        for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+            let (y, u, v) = rgb_to_ycbcr(pixel[0], pixel[1], pixel[2]);
+            y_plane[i] = y;
+            u_plane[i] = u;
+            v_plane[i] = v;
+        }
+
+        let Some(video) = &mut self.video else {
+            return;
+        };
+        let result = video
+            .write_all(b"FRAME\n")
+            .and_then(|_| video.write_all(&frame));
+        if let Err(err) = result {
+            self.fail(err.into());
+        }
+    }
+
+    /// Adds an APU sample to the audio.
+    pub fn write_sample(&mut self, sample: f32) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = match self.kind {
+            RecordingKind::Video => {
+                let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                self.audio.write_sample(sample)
+            }
+            RecordingKind::Audio => self.audio.write_sample(sample),
+        };
+        if let Err(err) = result {
+            self.fail(err.into());
+        }
+    }
+
+    /// Finishes the files, returning the path of the video, or the audio if only audio was
+    /// recorded.
+    pub fn finish(self) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        if let Some(mut video) = self.video {
+            video.flush()?;
+        }
+        self.audio.finalize()?;
+        Ok(self.path)
+    }
+
+    fn fail(&mut self, err: Box<dyn Error>) {
+        tracing::error!("error writing recording: {}", err);
+        self.error = Some(err);
+    }
+}
+
+/// Passes samples on to another sink while also adding them to a recording.
+pub struct RecordingSink<'a, S> {
+    inner: &'a mut S,
+    recorder: Option<&'a mut Recorder>,
+}
+
+impl<'a, S> RecordingSink<'a, S> {
+    pub fn new(inner: &'a mut S, recorder: Option<&'a mut Recorder>) -> Self {
+        RecordingSink { inner, recorder }
+    }
+}
+
+impl<S: AudioSink> AudioSink for RecordingSink<'_, S> {
+    fn write_sample(&mut self, sample: f32) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write_sample(sample);
+        }
+        self.inner.write_sample(sample);
+    }
+
+    fn samples_written(&self) -> usize {
+        self.inner.samples_written()
+    }
+}
+
+/// Converts to limited range BT.601, which players assume for standard definition video.
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (f32::from(r), f32::from(g), f32::from(b));
+    let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+    let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+    let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+    (y.round() as u8, u.round() as u8, v.round() as u8)
+}
@@ -0,0 +1,208 @@
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
+use std::collections::VecDeque;
+use std::io;
+
+/// The numbers of frames between snapshots offered in the settings.
+pub const INTERVALS: [u32; 6] = [1, 2, 3, 5, 10, 30];
+
+/// The buffer sizes offered in the settings, in megabytes. Zero turns rewinding off.
+pub const BUFFER_SIZES_MB: [u32; 6] = [0, 16, 32, 64, 128, 256];
+
+/// A compressed snapshot difference, stored as the deflated XOR between two serialized states.
+struct Delta {
+    previous_len: usize,
+    data: Vec<u8>,
+}
+
+/// Ring buffer of emulator snapshots used for rewinding.
+///
+/// Only the most recent snapshot is kept in full. Every older snapshot is stored as a compressed
+/// delta against the one that followed it, so walking backwards undoes one delta at a time. When
+/// the memory budget is exceeded the oldest deltas are dropped, though the latest snapshot is
+/// always kept.
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    /// The memory taken by the latest snapshot and every delta.
+    used_bytes: usize,
+    budget_bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            latest: None,
+            deltas: VecDeque::new(),
+            used_bytes: 0,
+            budget_bytes,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget_bytes
+    }
+
+    pub fn set_budget(&mut self, budget_bytes: usize) {
+        self.budget_bytes = budget_bytes;
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used_bytes = 0;
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let data = compress_to_vec(&xor(&previous, &state), 1);
+
+            self.used_bytes -= previous.len();
+            self.used_bytes += data.len();
+            self.deltas.push_back(Delta {
+                previous_len: previous.len(),
+                data,
+            });
+        }
+        self.used_bytes += state.len();
+        self.latest = Some(state);
+        self.evict();
+    }
+
+    /// Returns the most recent snapshot and steps the buffer back to the one before it. Once the
+    /// oldest snapshot is reached it keeps being returned.
+    pub fn pop(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some(delta) = self.deltas.pop_back() else {
+            return Ok(self.latest.clone());
+        };
+        let Some(latest) = self.latest.take() else {
+            return Ok(None);
+        };
+
+        self.used_bytes -= delta.data.len() + latest.len();
+
+        let diff = decompress_to_vec(&delta.data)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "corrupt rewind snapshot"))?;
+        let mut previous = xor(&latest, &diff);
+        previous.truncate(delta.previous_len);
+        self.used_bytes += previous.len();
+        self.latest = Some(previous);
+
+        Ok(Some(latest))
+    }
+
+    fn evict(&mut self) {
+        while self.used_bytes > self.budget_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used_bytes -= delta.data.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A snapshot that deflates badly, so that its delta against the one before it is large.
+    fn snapshot(seed: u8, len: usize) -> Vec<u8> {
+        let mut value = seed as u32;
+        (0..len)
+            .map(|_| {
+                value = value.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (value >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pops_snapshots_newest_first() {
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        let states: Vec<_> = (0..4).map(|seed| snapshot(seed, 64)).collect();
+        for state in &states {
+            buffer.push(state.clone());
+        }
+
+        for state in states.iter().rev() {
+            assert_eq!(buffer.pop().unwrap().as_ref(), Some(state));
+        }
+        // The oldest snapshot is returned again once there's nothing before it.
+        assert_eq!(buffer.pop().unwrap().as_ref(), Some(&states[0]));
+    }
+
+    #[test]
+    fn restores_snapshots_of_different_lengths() {
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        buffer.push(snapshot(1, 100));
+        buffer.push(snapshot(2, 40));
+        buffer.push(snapshot(3, 70));
+
+        assert_eq!(buffer.pop().unwrap(), Some(snapshot(3, 70)));
+        assert_eq!(buffer.pop().unwrap(), Some(snapshot(2, 40)));
+        assert_eq!(buffer.pop().unwrap(), Some(snapshot(1, 100)));
+    }
+
+    #[test]
+    fn empty_buffer_pops_nothing() {
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        assert_eq!(buffer.pop().unwrap(), None);
+
+        buffer.push(snapshot(0, 16));
+        buffer.clear();
+        assert_eq!(buffer.pop().unwrap(), None);
+        assert_eq!(buffer.used_bytes, 0);
+    }
+
+    #[test]
+    fn counts_the_latest_snapshot() {
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        buffer.push(snapshot(0, 256));
+        assert_eq!(buffer.used_bytes, 256);
+
+        buffer.push(snapshot(1, 128));
+        let delta_len = buffer.deltas[0].data.len();
+        assert_eq!(buffer.used_bytes, 128 + delta_len);
+
+        buffer.pop().unwrap();
+        assert_eq!(buffer.used_bytes, 256);
+    }
+
+    #[test]
+    fn evicts_the_oldest_snapshots() {
+        let len = 1024;
+        let mut buffer = RewindBuffer::new(len * 4);
+        for seed in 0..16 {
+            buffer.push(snapshot(seed, len));
+            assert!(buffer.used_bytes <= buffer.budget());
+        }
+        assert!(buffer.deltas.len() < 15);
+
+        // Only the most recent snapshots are left, ending with the oldest one kept.
+        let kept = buffer.deltas.len() as u8;
+        for seed in (15 - kept..16).rev() {
+            assert_eq!(buffer.pop().unwrap(), Some(snapshot(seed, len)));
+        }
+        assert_eq!(buffer.pop().unwrap(), Some(snapshot(15 - kept, len)));
+    }
+
+    #[test]
+    fn keeps_the_latest_snapshot_over_budget() {
+        let mut buffer = RewindBuffer::new(16);
+        buffer.push(snapshot(0, 64));
+        buffer.push(snapshot(1, 64));
+
+        assert!(buffer.deltas.is_empty());
+        assert_eq!(buffer.pop().unwrap(), Some(snapshot(1, 64)));
+
+        buffer.set_budget(0);
+        assert_eq!(buffer.pop().unwrap(), Some(snapshot(1, 64)));
+    }
+}
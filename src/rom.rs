@@ -0,0 +1,368 @@
+use crate::romdb::GameInfo;
+use md5::Md5;
+use rustednes_core::cartridge::Cartridge;
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+pub const INES_HEADER_LEN: usize = 16;
+pub const INES_MAGIC: &[u8; 4] = b"NES\x1a";
+/// File extensions of uncompressed ROMs, shared by the open dialog, drag and drop and the
+/// library.
+pub const EXTENSIONS: &[&str] = &["nes", "rom"];
+
+const TRAINER_LEN: usize = 512;
+const PRG_ROM_BANK_LEN: usize = 0x4000;
+const CHR_ROM_BANK_LEN: usize = 0x2000;
+
+#[derive(Debug)]
+pub struct Rom {
+    pub cartridge: Cartridge,
+    pub info: RomInfo,
+}
+
+/// Everything known about a loaded ROM besides its contents.
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    pub header: RomHeader,
+    /// The header as it was in the file, if the ROM database corrected it.
+    pub original_header: Option<RomHeader>,
+    pub checksums: Checksums,
+    /// The file inside the archive the ROM was picked from, if there was a choice of several.
+    pub entry: Option<String>,
+    /// The patch that was applied to the ROM when it was loaded.
+    pub patch: Option<PathBuf>,
+    /// A patch next to the ROM that couldn't be applied, so the ROM was loaded without it.
+    pub skipped_patch: Option<PathBuf>,
+    /// The ROM database entry matching the ROM's contents.
+    pub game: Option<GameInfo>,
+}
+
+/// Checksums of the PRG and CHR ROM, leaving out the header so they match headerless dumps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksums {
+    pub crc32: u32,
+    pub sha1: String,
+    /// Used by FM2 movies to check they're played back with the right ROM.
+    pub md5: [u8; 16],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    /// Works on both NTSC and PAL consoles.
+    Multi,
+    Dendy,
+}
+
+/// Fields of an iNES header that the frontend cares about. The cartridge itself is parsed by
+/// `rustednes_core`; this is only used for things the core doesn't expose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomHeader {
+    pub mapper: u16,
+    pub has_battery: bool,
+    pub mirroring: Mirroring,
+    pub region: Region,
+    pub has_trainer: bool,
+    pub prg_rom_len: usize,
+    pub chr_rom_len: usize,
+    /// Whether this is a NES 2.0 header rather than the original iNES format.
+    pub nes2: bool,
+}
+
+impl RomHeader {
+    pub fn parse(data: &[u8]) -> Result<RomHeader, InvalidHeader> {
+        if data.len() < INES_HEADER_LEN || &data[0..4] != INES_MAGIC {
+            return Err(InvalidHeader);
+        }
+
+        let nes2 = data[7] & 0x0c == 0x08;
+
+        let mut mapper = u16::from((data[6] >> 4) | (data[7] & 0xf0));
+        let mut prg_banks = usize::from(data[4]);
+        let mut chr_banks = usize::from(data[5]);
+        let region;
+        if nes2 {
+            // NES 2.0 headers carry four more mapper bits and the high bits of the ROM sizes.
+            mapper |= u16::from(data[8] & 0x0f) << 8;
+            prg_banks |= usize::from(data[9] & 0x0f) << 8;
+            chr_banks |= usize::from(data[9] >> 4) << 8;
+            region = match data[12] & 0x03 {
+                0 => Region::Ntsc,
+                1 => Region::Pal,
+                2 => Region::Multi,
+                _ => Region::Dendy,
+            };
+        } else if data[9] & 0x01 != 0 {
+            region = Region::Pal;
+        } else {
+            region = Region::Ntsc;
+        }
+
+        let mirroring = if data[6] & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if data[6] & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        Ok(RomHeader {
+            mapper,
+            has_battery: data[6] & 0x02 != 0,
+            mirroring,
+            region,
+            has_trainer: data[6] & 0x04 != 0,
+            prg_rom_len: prg_banks * PRG_ROM_BANK_LEN,
+            chr_rom_len: chr_banks * CHR_ROM_BANK_LEN,
+            nes2,
+        })
+    }
+
+    /// Checksums the PRG and CHR ROM, which identify a game regardless of its header.
+    pub fn checksums(&self, data: &[u8]) -> Checksums {
+        let start = INES_HEADER_LEN + if self.has_trainer { TRAINER_LEN } else { 0 };
+        let end = (start + self.prg_rom_len + self.chr_rom_len).min(data.len());
+        let prg_chr = data.get(start..end).unwrap_or_default();
+
+        let sha1 = Sha1::digest(prg_chr)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        Checksums {
+            crc32: crc32fast::hash(prg_chr),
+            sha1,
+            md5: Md5::digest(prg_chr).into(),
+        }
+    }
+
+    /// Whether the header disagrees with what the database knows about the game.
+    pub fn differs_from(&self, game: &GameInfo) -> bool {
+        self.mapper != game.mapper
+            || game
+                .mirroring
+                .is_some_and(|mirroring| mirroring != self.mirroring)
+            || self.has_battery != game.has_battery
+            || self.region != game.region
+    }
+}
+
+/// Rewrites the header at the start of `data` as a NES 2.0 header using the values from the
+/// database. ROM sizes and the trainer flag are kept, as is the mirroring if the database leaves
+/// it to the mapper. Everything else is cleared, which also gets rid of junk like "DiskDude!"
+/// that some old tools wrote into the unused bytes.
+pub fn correct_header(data: &mut [u8], header: &RomHeader, game: &GameInfo) {
+    let prg_banks = header.prg_rom_len / PRG_ROM_BANK_LEN;
+    let chr_banks = header.chr_rom_len / CHR_ROM_BANK_LEN;
+    let mapper = game.mapper;
+
+    let mut flags_6 = ((mapper & 0x0f) as u8) << 4;
+    match game.mirroring.unwrap_or(header.mirroring) {
+        Mirroring::Horizontal => {}
+        Mirroring::Vertical => flags_6 |= 0x01,
+        Mirroring::FourScreen => flags_6 |= 0x08,
+    }
+    if game.has_battery {
+        flags_6 |= 0x02;
+    }
+    if header.has_trainer {
+        flags_6 |= 0x04;
+    }
+
+    // Assume the common 8 KiB of PRG-RAM, battery-backed if the game saves, and 8 KiB of
+    // CHR-RAM for games without CHR-ROM. Sizes are stored as shift counts of 64 bytes.
+    let prg_ram = if game.has_battery { 0x70 } else { 0x07 };
+    let chr_ram = if chr_banks == 0 { 0x07 } else { 0x00 };
+
+    let region = match game.region {
+        Region::Ntsc => 0,
+        Region::Pal => 1,
+        Region::Multi => 2,
+        Region::Dendy => 3,
+    };
+
+    data[4] = prg_banks as u8;
+    data[5] = chr_banks as u8;
+    data[6] = flags_6;
+    data[7] = (mapper & 0xf0) as u8 | 0x08;
+    data[8] = ((mapper >> 8) & 0x0f) as u8;
+    data[9] = ((chr_banks >> 8) as u8) << 4 | (prg_banks >> 8) as u8;
+    data[10] = prg_ram;
+    data[11] = chr_ram;
+    data[12] = region;
+    data[13..INES_HEADER_LEN].fill(0);
+}
+
+/// The board name of the more common mappers.
+pub fn mapper_name(mapper: u16) -> Option<&'static str> {
+    Some(match mapper {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        34 => "BNROM / NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidHeader;
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "missing iNES header")
+    }
+}
+
+impl Error for InvalidHeader {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ines(flags_6: u8, flags_7: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut data = vec![0; INES_HEADER_LEN];
+        data[..4].copy_from_slice(INES_MAGIC);
+        data[4] = prg_banks;
+        data[5] = chr_banks;
+        data[6] = flags_6;
+        data[7] = flags_7;
+        data
+    }
+
+    fn game(mirroring: Option<Mirroring>) -> GameInfo {
+        GameInfo {
+            title: "Test".to_string(),
+            mapper: 0x14a,
+            mirroring,
+            has_battery: true,
+            region: Region::Pal,
+        }
+    }
+
+    #[test]
+    fn parses_ines_header() {
+        let header = RomHeader::parse(&ines(0x13, 0x40, 2, 1)).unwrap();
+        assert_eq!(
+            header,
+            RomHeader {
+                mapper: 0x41,
+                has_battery: true,
+                mirroring: Mirroring::Vertical,
+                region: Region::Ntsc,
+                has_trainer: false,
+                prg_rom_len: 2 * PRG_ROM_BANK_LEN,
+                chr_rom_len: CHR_ROM_BANK_LEN,
+                nes2: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_nes2_header() {
+        let mut data = ines(0x0c, 0x18, 1, 2);
+        data[8] = 0x02;
+        data[9] = 0x21;
+        data[12] = 0x03;
+
+        let header = RomHeader::parse(&data).unwrap();
+        assert_eq!(header.mapper, 0x210);
+        assert_eq!(header.mirroring, Mirroring::FourScreen);
+        assert_eq!(header.region, Region::Dendy);
+        assert!(header.has_trainer);
+        assert!(header.nes2);
+        assert_eq!(header.prg_rom_len, 0x101 * PRG_ROM_BANK_LEN);
+        assert_eq!(header.chr_rom_len, 0x202 * CHR_ROM_BANK_LEN);
+    }
+
+    #[test]
+    fn ignores_nes2_fields_in_ines_header() {
+        // Junk in bytes 8 to 15 of an old header, as left by tools like "DiskDude!".
+        let mut data = ines(0x00, 0x00, 1, 1);
+        data[7..].copy_from_slice(b"DiskDude!");
+
+        let header = RomHeader::parse(&data).unwrap();
+        assert!(!header.nes2);
+        assert_eq!(header.mapper, u16::from(b'D' & 0xf0));
+        assert_eq!(header.region, Region::Pal);
+        assert_eq!(header.prg_rom_len, PRG_ROM_BANK_LEN);
+    }
+
+    #[test]
+    fn rejects_missing_header() {
+        assert_eq!(RomHeader::parse(b"NES\x1a"), Err(InvalidHeader));
+
+        let mut data = ines(0, 0, 1, 1);
+        data[3] = 0;
+        assert_eq!(RomHeader::parse(&data), Err(InvalidHeader));
+    }
+
+    #[test]
+    fn checksums_leave_out_header_and_trainer() {
+        let prg_chr = [0x5a; PRG_ROM_BANK_LEN + CHR_ROM_BANK_LEN];
+
+        let mut plain = ines(0x00, 0x00, 1, 1);
+        plain.extend_from_slice(&prg_chr);
+        let mut with_trainer = ines(0x04, 0x00, 1, 1);
+        with_trainer.extend_from_slice(&[0xff; TRAINER_LEN]);
+        with_trainer.extend_from_slice(&prg_chr);
+
+        let plain = RomHeader::parse(&plain).unwrap().checksums(&plain);
+        let with_trainer = RomHeader::parse(&with_trainer)
+            .unwrap()
+            .checksums(&with_trainer);
+        assert_eq!(plain, with_trainer);
+        assert_eq!(plain.crc32, crc32fast::hash(&prg_chr));
+    }
+
+    #[test]
+    fn corrects_header_from_database() {
+        let mut data = ines(0x05, 0x00, 2, 0);
+        data[7..].copy_from_slice(b"DiskDude!");
+        let header = RomHeader::parse(&data).unwrap();
+        let game = game(Some(Mirroring::FourScreen));
+
+        correct_header(&mut data, &header, &game);
+        let corrected = RomHeader::parse(&data).unwrap();
+        assert!(!corrected.differs_from(&game));
+        assert!(corrected.nes2);
+        assert!(corrected.has_trainer);
+        assert_eq!(corrected.prg_rom_len, header.prg_rom_len);
+        assert_eq!(corrected.chr_rom_len, 0);
+        // Battery-backed PRG-RAM and CHR-RAM, since there's no CHR-ROM.
+        assert_eq!(data[10], 0x70);
+        assert_eq!(data[11], 0x07);
+        assert_eq!(&data[13..], &[0; 3]);
+    }
+
+    #[test]
+    fn correcting_keeps_mirroring_left_to_mapper() {
+        let mut data = ines(0x01, 0x00, 1, 1);
+        let header = RomHeader::parse(&data).unwrap();
+        let game = game(None);
+
+        correct_header(&mut data, &header, &game);
+        let corrected = RomHeader::parse(&data).unwrap();
+        assert_eq!(corrected.mirroring, Mirroring::Vertical);
+        assert!(!corrected.differs_from(&game));
+    }
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! A bundled database of known games, keyed by the CRC32 of their PRG and CHR ROM.
+
+use crate::rom::{Mirroring, Region};
+use rust_embed::RustEmbed;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+#[derive(RustEmbed)]
+#[folder = "resources/romdb/"]
+struct RomDbFiles;
+
+static DATABASE: LazyLock<HashMap<u32, GameInfo>> = LazyLock::new(load);
+
+/// What the database knows about a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameInfo {
+    pub title: String,
+    pub mapper: u16,
+    /// `None` for games whose mapper switches mirroring itself, where the header bits don't
+    /// matter.
+    pub mirroring: Option<Mirroring>,
+    pub has_battery: bool,
+    pub region: Region,
+}
+
+pub fn lookup(prg_chr_crc: u32) -> Option<&'static GameInfo> {
+    DATABASE.get(&prg_chr_crc)
+}
+
+fn load() -> HashMap<u32, GameInfo> {
+    let mut database = HashMap::new();
+
+    for file_name in RomDbFiles::iter() {
+        let Some(file) = RomDbFiles::get(&file_name) else {
+            continue;
+        };
+        let contents = String::from_utf8_lossy(&file.data);
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match parse_line(line) {
+                Some((crc, game)) => {
+                    database.insert(crc, game);
+                }
+                None => {
+                    tracing::warn!("invalid ROM database entry {file_name}:{}", line_number + 1)
+                }
+            }
+        }
+    }
+
+    database
+}
+
+/// Parses a `crc,mapper,mirroring,battery,region,title` line.
+fn parse_line(line: &str) -> Option<(u32, GameInfo)> {
+    let mut fields = line.splitn(6, ',').map(str::trim);
+
+    let crc = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let mapper = fields.next()?.parse().ok()?;
+    let mirroring = match fields.next()? {
+        "H" => Some(Mirroring::Horizontal),
+        "V" => Some(Mirroring::Vertical),
+        "4" => Some(Mirroring::FourScreen),
+        "-" => None,
+        _ => return None,
+    };
+    let has_battery = match fields.next()? {
+        "0" => false,
+        "1" => true,
+        _ => return None,
+    };
+    let region = match fields.next()? {
+        "NTSC" => Region::Ntsc,
+        "PAL" => Region::Pal,
+        "Multi" => Region::Multi,
+        "Dendy" => Region::Dendy,
+        _ => return None,
+    };
+    let title = fields.next()?.to_string();
+
+    Some((
+        crc,
+        GameInfo {
+            title,
+            mapper,
+            mirroring,
+            has_battery,
+            region,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_line() {
+        let (crc, game) = parse_line("0021ED29,1,-,1,PAL,Reigen Doushi (Japan)").unwrap();
+        assert_eq!(crc, 0x0021ed29);
+        assert_eq!(
+            game,
+            GameInfo {
+                title: "Reigen Doushi (Japan)".to_string(),
+                mapper: 1,
+                mirroring: None,
+                has_battery: true,
+                region: Region::Pal,
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_commas_in_title() {
+        let (_, game) = parse_line("FB98D46E, 0, H, 0, Multi, Ice Climber (USA, Europe)").unwrap();
+        assert_eq!(game.title, "Ice Climber (USA, Europe)");
+        assert_eq!(game.mirroring, Some(Mirroring::Horizontal));
+        assert_eq!(game.region, Region::Multi);
+    }
+
+    #[test]
+    fn parses_every_mirroring_and_region() {
+        for (field, mirroring) in [
+            ("H", Some(Mirroring::Horizontal)),
+            ("V", Some(Mirroring::Vertical)),
+            ("4", Some(Mirroring::FourScreen)),
+            ("-", None),
+        ] {
+            let (_, game) = parse_line(&format!("0,0,{field},0,NTSC,Game")).unwrap();
+            assert_eq!(game.mirroring, mirroring);
+        }
+        for (field, region) in [
+            ("NTSC", Region::Ntsc),
+            ("PAL", Region::Pal),
+            ("Multi", Region::Multi),
+            ("Dendy", Region::Dendy),
+        ] {
+            let (_, game) = parse_line(&format!("0,0,H,0,{field},Game")).unwrap();
+            assert_eq!(game.region, region);
+        }
+    }
+
+    #[test]
+    fn bundled_database_parses() {
+        let database = load();
+        let lines = RomDbFiles::iter()
+            .filter_map(|file_name| RomDbFiles::get(&file_name))
+            .map(|file| {
+                String::from_utf8_lossy(&file.data)
+                    .lines()
+                    .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+                    .count()
+            })
+            .sum();
+        assert_eq!(database.len(), lines);
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for line in [
+            "",
+            "0021ED29,1,-,0,NTSC",
+            "XYZ,1,-,0,NTSC,Game",
+            "0021ED29,mmc1,-,0,NTSC,Game",
+            "0021ED29,1,S,0,NTSC,Game",
+            "0021ED29,1,-,yes,NTSC,Game",
+            "0021ED29,1,-,0,SECAM,Game",
+        ] {
+            assert_eq!(parse_line(line), None, "{line}");
+        }
+    }
+}
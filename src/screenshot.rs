@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Saves the emulator's screen as PNG images.
+
+use crate::capture;
+use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Sizes screenshots can be saved at, as multiples of the NES resolution.
+pub const SCALES: [u32; 4] = [1, 2, 3, 4];
+
+/// The directory screenshots are saved to when the config doesn't name one.
+pub fn default_dir() -> PathBuf {
+    dirs::picture_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join("RustedNES")
+}
+
+/// Writes an RGBA frame to a PNG in `dir`, named after the ROM and the current time, and returns
+/// the path of the new file. Each pixel is repeated `scale` times in both directions. Wide frames
+/// from the NTSC filter are stretched to a matching height so the picture keeps its shape.
+pub fn save(
+    pixels: &[u8],
+    scale: u32,
+    dir: &Path,
+    rom_path: &Path,
+) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let name = capture::capture_name(dir, rom_path, &["png"]);
+    let path = dir.join(format!("{name}.png"));
+
+    let scale = scale.max(1) as usize;
+    let frame_width = pixels.len() / (SCREEN_HEIGHT * 4);
+    let width = frame_width * scale;
+    let height = (SCREEN_HEIGHT * scale) as f32 * frame_width as f32 / SCREEN_WIDTH as f32;
+    let height = height.round() as usize;
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(&path)?),
+        width as u32,
+        height as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+
+    let rows: Vec<_> = pixels.chunks_exact(frame_width * 4).collect();
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for pixel in rows[y * rows.len() / height].chunks_exact(4) {
+            for _ in 0..scale {
+                data.extend_from_slice(&pixel[..3]);
+            }
+        }
+    }
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+
+    Ok(path)
+}
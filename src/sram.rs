@@ -0,0 +1,39 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Returns the `.sav` file that belongs to the ROM at `rom_path`. ROMs picked out of an archive
+/// holding several games have the name of their `entry` added, so each game has its own save.
+pub fn sav_path(rom_path: &Path, entry: Option<&str>) -> PathBuf {
+    let entry_stem = entry.and_then(|entry| Path::new(entry).file_stem());
+    match (rom_path.file_stem(), entry_stem) {
+        (Some(stem), Some(entry_stem)) => rom_path.with_file_name(format!(
+            "{} ({}).sav",
+            stem.to_string_lossy(),
+            entry_stem.to_string_lossy()
+        )),
+        _ => rom_path.with_extension("sav"),
+    }
+}
+
+/// Reads a save file, returning `None` if the ROM hasn't been saved yet.
+pub fn load(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes the save file through a temporary file that is renamed into place, so a crash
+/// part-way through never leaves a truncated save behind.
+pub fn save(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("sav.tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)
+}
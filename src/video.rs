@@ -1,16 +1,33 @@
 use std::mem;
 
-use rustednes_core::sink::{VideoSink, XRGB8888_PALETTE};
+use rustednes_core::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use rustednes_core::sink::VideoSink;
+
+use crate::ntsc::{NtscFilter, NTSC_WIDTH};
+use crate::palette::Palette;
 
 pub struct VideoFrameSink<'a> {
-    pixels: &'a mut [u8],
+    /// RGBA pixels, resized to fit each frame as it's written.
+    pixels: &'a mut Vec<u8>,
+    palette: &'a Palette,
+    ntsc: Option<&'a mut NtscFilter>,
+    /// The PPU's colour emphasis bits, which apply to the whole frame.
+    emphasis: u8,
     frame_written: bool,
 }
 
 impl<'a> VideoFrameSink<'a> {
-    pub fn new(pixels: &'a mut [u8]) -> Self {
+    pub fn new(
+        pixels: &'a mut Vec<u8>,
+        palette: &'a Palette,
+        ntsc: Option<&'a mut NtscFilter>,
+        emphasis: u8,
+    ) -> Self {
         VideoFrameSink {
             pixels,
+            palette,
+            ntsc,
+            emphasis,
             frame_written: false,
         }
     }
@@ -18,13 +35,21 @@ impl<'a> VideoFrameSink<'a> {
 
 impl<'a> VideoSink for VideoFrameSink<'a> {
     fn write_frame(&mut self, frame_buffer: &[u8]) {
+        if let Some(ntsc) = &mut self.ntsc {
+            self.pixels.resize(NTSC_WIDTH * SCREEN_HEIGHT * 4, 0xFF);
+            ntsc.render(frame_buffer, self.emphasis, self.pixels);
+            self.frame_written = true;
+            return;
+        }
+
+        self.pixels.resize(SCREEN_WIDTH * SCREEN_HEIGHT * 4, 0xFF);
         for (i, palette_index) in frame_buffer.iter().enumerate() {
-            let pixel = XRGB8888_PALETTE[*palette_index as usize];
+            let [r, g, b] = self.palette.rgb(*palette_index, self.emphasis);
             let offset = i * 4;
 
-            self.pixels[offset] = (pixel >> 16) as u8;
-            self.pixels[offset + 1] = (pixel >> 8) as u8;
-            self.pixels[offset + 2] = pixel as u8;
+            self.pixels[offset] = r;
+            self.pixels[offset + 1] = g;
+            self.pixels[offset + 2] = b;
             self.pixels[offset + 3] = 0xFF;
         }
         self.frame_written = true;